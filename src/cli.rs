@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use eframe::egui::Color32;
+
+use crate::export::{FieldFormat, LogFormat, RecordFormat};
+use crate::render::{ColormapKind, RenderField};
+use crate::simulation::brush::BrushShape;
+use crate::settings::{SettingsFile, SimulationSettings};
+
+/// Command line flags, each one overriding the matching field of the saved settings.
+#[derive(Parser, Debug)]
+#[command(version, about = "A 100% rust incompressible fluid simulation")]
+pub struct Cli {
+    /// Start from a preset of the settings file instead of the last used settings
+    #[arg(long)]
+    pub preset: Option<String>,
+    /// Start from the default settings instead of the last used settings
+    #[arg(long)]
+    pub defaults: bool,
+    /// Skip the settings panel and go straight to the simulation
+    #[arg(long)]
+    pub no_launcher: bool,
+    /// Run a scenario file: domain, obstacles, emitters, forces and solver settings
+    #[arg(long)]
+    pub scenario: Option<PathBuf>,
+    /// Start from a snapshot file saved with F5
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+    /// Replay an input recording made with P, from the state it started from
+    #[arg(long, conflicts_with_all = ["snapshot", "scenario"])]
+    pub replay: Option<PathBuf>,
+    /// Append the diagnostics of every step (mass, energy, divergence, residuals, timings) to this file,
+    /// a headless run then only records frames when --output is also given
+    #[arg(long)]
+    pub diagnostics: Option<PathBuf>,
+
+    /// Run without a window and record the run in the record format
+    #[arg(long, help_heading = "Headless")]
+    pub headless: bool,
+    /// Number of simulation steps of a headless run [default: 300, the length of the replay or the scenario
+    /// duration]
+    #[arg(long, help_heading = "Headless")]
+    pub steps: Option<usize>,
+    /// Fixed time step of a headless run, in seconds
+    #[arg(long, default_value_t = 1.0 / 30.0, value_parser = parse_time_step, help_heading = "Headless")]
+    pub dt: f64,
+    /// Recording path of a headless run, a directory for PNG frames
+    #[arg(long, help_heading = "Headless")]
+    pub output: Option<PathBuf>,
+    /// Export the simulation fields of a headless run to this directory, instead of recording
+    /// frames unless --output is also given
+    #[arg(long, help_heading = "Headless")]
+    pub export_fields: Option<PathBuf>,
+
+    /// Simulation view width in pixels
+    #[arg(long)]
+    pub width: Option<usize>,
+    /// Simulation view height in pixels
+    #[arg(long)]
+    pub height: Option<usize>,
+    /// Mouse radius in pixels
+    #[arg(long)]
+    pub particle_radius: Option<usize>,
+    /// Shape and falloff of the mouse brush
+    #[arg(long, value_enum)]
+    pub brush_shape: Option<BrushShape>,
+    /// Density painted per second at the brush center
+    #[arg(long)]
+    pub brush_strength: Option<f64>,
+    /// Share of the mouse velocity given to the fluid at the brush center
+    #[arg(long)]
+    pub brush_force: Option<f64>,
+    /// Make left click remove density instead of adding it
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub eraser: Option<bool>,
+    /// Pixels per grid cell
+    #[arg(long)]
+    pub precision: Option<usize>,
+    /// Initial density of every cell
+    #[arg(long)]
+    pub start_density: Option<f64>,
+    /// How fast the density spreads
+    #[arg(long)]
+    pub diffusion_rate: Option<f64>,
+    /// Start from random density blobs instead of the start density
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub randomize: Option<bool>,
+    /// Number of random density seeds
+    #[arg(long)]
+    pub random_smoothing: Option<usize>,
+    /// Pressure solver iterations per step
+    #[arg(long)]
+    pub pressure_iters: Option<usize>,
+    /// Diffusion solver iterations per step
+    #[arg(long)]
+    pub diffusion_iters: Option<usize>,
+    /// Correct the density after each step so advection and diffusion don't lose or gain dye
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub conserve_mass: Option<bool>,
+    /// Colormap used to render the field
+    #[arg(long, value_enum)]
+    pub colormap: Option<ColormapKind>,
+    /// Custom gradient colors from low to high, as comma separated `#RRGGBB`
+    #[arg(long, value_delimiter = ',', value_parser = parse_color)]
+    pub gradient: Option<Vec<Color32>>,
+    /// Field shown on screen
+    #[arg(long, value_enum)]
+    pub field: Option<RenderField>,
+    /// Render the velocity with Line Integral Convolution
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub lic: Option<bool>,
+    /// Modulate the LIC texture by density
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub lic_modulate: Option<bool>,
+    /// Bilinearly interpolate the field instead of drawing blocky cells
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub smooth: Option<bool>,
+    /// Record one frame every N simulation steps
+    #[arg(long)]
+    pub record_stride: Option<usize>,
+    /// Record the field at grid resolution instead of the rendered view
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub record_grid: Option<bool>,
+    /// Output format of recordings
+    #[arg(long, value_enum)]
+    pub record_format: Option<RecordFormat>,
+    /// Scale factor applied to recorded frames
+    #[arg(long)]
+    pub record_scale: Option<f64>,
+    /// File format of field exports
+    #[arg(long, value_enum)]
+    pub field_format: Option<FieldFormat>,
+    /// Export the fields every N simulation steps
+    #[arg(long)]
+    pub field_stride: Option<usize>,
+    /// File format of diagnostics logs whose path doesn't end in .csv or .jsonl
+    #[arg(long, value_enum)]
+    pub log_format: Option<LogFormat>,
+    /// Density added per second by emitters placed with N
+    #[arg(long)]
+    pub emitter_density: Option<f64>,
+    /// Speed of the flow out of placed emitters, in cells per second
+    #[arg(long)]
+    pub emitter_speed: Option<f64>,
+    /// Direction of the flow out of placed emitters, in degrees counterclockwise from the right
+    #[arg(long, allow_negative_numbers = true)]
+    pub emitter_angle: Option<f64>,
+    /// Density removed per second by sinks placed with M
+    #[arg(long)]
+    pub sink_rate: Option<f64>,
+}
+
+impl Cli {
+    /// Picks the base settings (last used, preset or defaults) and applies the flags on top.
+    pub fn settings(&self, file: &SettingsFile) -> Result<SimulationSettings, String> {
+        let mut settings = match &self.preset {
+            Some(name) => file.presets.get(name).cloned().ok_or_else(|| format!("unknown preset \"{name}\""))?,
+            None if self.defaults => SimulationSettings::default(),
+            None => file.current.clone(),
+        };
+
+        macro_rules! apply {
+            ($($field:ident),*) => {
+                $(if let Some(value) = &self.$field {
+                    settings.$field = value.clone();
+                })*
+            };
+        }
+        apply!(width, height, particle_radius, brush_shape, brush_strength, brush_force, eraser, precision, start_density,
+            diffusion_rate, randomize, random_smoothing, pressure_iters, diffusion_iters, conserve_mass, colormap, gradient,
+            field, lic, lic_modulate, smooth, record_stride, record_grid, record_format, record_scale, field_format,
+            field_stride, log_format, emitter_density, emitter_speed, emitter_angle, sink_rate);
+
+        if settings.gradient.len() < 2 {
+            return Err("the gradient needs at least two colors".to_string());
+        }
+        if settings.width < 10 || settings.height < 10 || settings.precision == 0 {
+            return Err("the simulation needs a size of at least 10x10 pixels and a precision of at least 1".to_string());
+        }
+
+        Ok(settings)
+    }
+}
+
+fn parse_color(s: &str) -> Result<Color32, String> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(format!("expected a #RRGGBB color, got \"{s}\""));
+    }
+    let rgb = u32::from_str_radix(hex, 16).map_err(|_| format!("expected a #RRGGBB color, got \"{s}\""))?;
+    Ok(Color32::from_rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+fn parse_time_step(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(dt) if dt > 0.0 && dt.is_finite() => Ok(dt),
+        _ => Err(format!("expected a positive time step in seconds, got \"{s}\"")),
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::diagnostics::FlowStats;
+use crate::simulation::fluid_sim::FluidSim;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+pub enum LogFormat {
+    /// A CSV table with a row per step
+    Csv,
+    /// A JSON object per line and step
+    Jsonl,
+}
+
+impl LogFormat {
+    pub const ALL: [LogFormat; 2] = [LogFormat::Csv, LogFormat::Jsonl];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogFormat::Csv => "CSV",
+            LogFormat::Jsonl => "JSON Lines",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            LogFormat::Jsonl => "jsonl",
+        }
+    }
+
+    /// The format a path's extension stands for, if any.
+    pub fn from_path(path: &Path) -> Option<LogFormat> {
+        let extension = path.extension()?.to_str()?;
+        Self::ALL.into_iter().find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+}
+
+/// Diagnostics of one simulated step. Timings are in seconds.
+#[derive(Clone, Debug, Serialize)]
+pub struct StepDiagnostics {
+    /// When the log the step belongs to was started, in milliseconds since the Unix
+    /// epoch, telling apart the runs appended to the same file.
+    pub run: u64,
+    pub step: usize,
+    pub time: f64,
+    pub dt: f64,
+    pub mass: f64,
+    pub mass_sources: f64,
+    pub mass_outflow: f64,
+    pub mass_drift: f64,
+    pub mass_correction: f64,
+    pub kinetic_energy: f64,
+    pub enstrophy: f64,
+    pub max_speed: f64,
+    pub max_divergence: f64,
+    pub pressure_residual: f64,
+    pub time_sources: f64,
+    pub time_advect_velocity: f64,
+    pub time_projection: f64,
+    pub time_diffusion: f64,
+    pub time_advect_density: f64,
+    pub time_step: f64,
+}
+
+impl StepDiagnostics {
+    /// Columns of the CSV log, in the order of the fields.
+    const COLUMNS: [&'static str; 20] = [
+        "run", "step", "time", "dt", "mass", "mass_sources", "mass_outflow", "mass_drift", "mass_correction",
+        "kinetic_energy", "enstrophy", "max_speed", "max_divergence", "pressure_residual", "time_sources",
+        "time_advect_velocity", "time_projection", "time_diffusion", "time_advect_density", "time_step",
+    ];
+
+    /// Diagnostics of the step the simulation just took, `time` being the time it ended at.
+    pub fn measure(fluid: &FluidSim, run: u64, step: usize, time: f64, dt: f64) -> Self {
+        let stats = FlowStats::measure(fluid);
+        let timings = &fluid.timings;
+        StepDiagnostics {
+            run,
+            step,
+            time,
+            dt,
+            mass: stats.mass,
+            mass_sources: fluid.mass.sources,
+            mass_outflow: fluid.mass.outflow,
+            mass_drift: fluid.mass.drift,
+            mass_correction: fluid.mass.correction,
+            kinetic_energy: stats.kinetic_energy,
+            enstrophy: stats.enstrophy,
+            max_speed: stats.max_speed,
+            // Measured at every step while logging
+            max_divergence: fluid.max_divergence.unwrap_or(f64::NAN),
+            pressure_residual: fluid.pressure_residual.unwrap_or(f64::NAN),
+            time_sources: timings.sources,
+            time_advect_velocity: timings.advect_velocity,
+            time_projection: timings.projection,
+            time_diffusion: timings.diffusion,
+            time_advect_density: timings.advect_density,
+            time_step: timings.total(),
+        }
+    }
+
+    fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let values = [
+            self.time, self.dt, self.mass, self.mass_sources, self.mass_outflow, self.mass_drift, self.mass_correction,
+            self.kinetic_energy, self.enstrophy, self.max_speed, self.max_divergence, self.pressure_residual,
+            self.time_sources, self.time_advect_velocity, self.time_projection, self.time_diffusion,
+            self.time_advect_density, self.time_step,
+        ];
+        write!(out, "{},{}", self.run, self.step)?;
+        for value in values {
+            write!(out, ",{value}")?;
+        }
+        writeln!(out)
+    }
+}
+
+/// Appends the diagnostics of every simulated step to a CSV or JSON lines file.
+///
+/// An existing log is appended to, a CSV log only gets its header when it starts empty.
+/// Every row carries the start time of its log to tell the runs apart.
+pub struct DiagnosticsLog {
+    pub path: PathBuf,
+    pub format: LogFormat,
+    pub run: u64,
+    pub written: usize,
+    out: BufWriter<File>,
+}
+
+impl DiagnosticsLog {
+    /// Starts a log in the format of the extension of `path`, or in `format` when it has none.
+    pub fn start(path: PathBuf, format: LogFormat) -> io::Result<Self> {
+        let format = LogFormat::from_path(&path).unwrap_or(format);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut out = BufWriter::new(file);
+        if empty && format == LogFormat::Csv {
+            writeln!(out, "{}", StepDiagnostics::COLUMNS.join(","))?;
+        }
+
+        Ok(DiagnosticsLog {
+            path,
+            format,
+            run: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+            written: 0,
+            out,
+        })
+    }
+
+    /// Logs the step the simulation just took.
+    pub fn append(&mut self, fluid: &FluidSim, time: f64, dt: f64) -> io::Result<()> {
+        let row = StepDiagnostics::measure(fluid, self.run, self.written, time, dt);
+        match self.format {
+            LogFormat::Csv => row.write_csv(&mut self.out)?,
+            LogFormat::Jsonl => {
+                serde_json::to_writer(&mut self.out, &row)?;
+                writeln!(self.out)?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
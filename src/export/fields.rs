@@ -0,0 +1,256 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::fluid_sim::FluidSim;
+
+/// Names of the exported fields, in the order they are written.
+pub const FIELD_NAMES: [&str; 5] = ["density", "velocity_x", "velocity_y", "pressure", "divergence"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+pub enum FieldFormat {
+    /// Legacy VTK image data, one file per step, readable by ParaView
+    Vtk,
+    /// One NumPy array per field and step, shaped (height, width)
+    Npy,
+    /// One CSV table per step with a row per cell
+    Csv,
+}
+
+impl FieldFormat {
+    pub const ALL: [FieldFormat; 3] = [FieldFormat::Vtk, FieldFormat::Npy, FieldFormat::Csv];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldFormat::Vtk => "VTK",
+            FieldFormat::Npy => "NumPy (.npy)",
+            FieldFormat::Csv => "CSV",
+        }
+    }
+}
+
+/// The exported fields of the current step, in `FIELD_NAMES` order.
+///
+/// The divergence is the one of the current velocity field, the solver's own
+/// buffer only holds the divergence it projected away.
+pub fn sample_fields(fluid: &FluidSim) -> [Vec<f64>; 5] {
+    let mut divergence = vec![0.0; fluid.width * fluid.height];
+    for y in 0..fluid.height {
+        for x in 0..fluid.width {
+            divergence[x + y * fluid.width] = fluid.velocity_divergence(x, y);
+        }
+    }
+
+    [
+        fluid.density.clone(),
+        fluid.velocity_x.clone(),
+        fluid.velocity_y.clone(),
+        fluid.pressure.clone(),
+        divergence,
+    ]
+}
+
+/// Writes the fields as legacy ASCII VTK structured points, with the velocity
+/// also given as a vector so ParaView can draw glyphs and streamlines from it.
+pub fn write_vtk<W: Write>(out: &mut W, width: usize, height: usize, time: f64, fields: &[Vec<f64>; 5]) -> io::Result<()> {
+    writeln!(out, "# vtk DataFile Version 3.0")?;
+    writeln!(out, "fluid-sim fields")?;
+    writeln!(out, "ASCII")?;
+    writeln!(out, "DATASET STRUCTURED_POINTS")?;
+    writeln!(out, "DIMENSIONS {width} {height} 1")?;
+    writeln!(out, "ORIGIN 0 0 0")?;
+    writeln!(out, "SPACING 1 1 1")?;
+    writeln!(out, "FIELD FieldData 1")?;
+    writeln!(out, "TIME 1 1 double")?;
+    writeln!(out, "{time}")?;
+    writeln!(out, "POINT_DATA {}", width * height)?;
+
+    for (name, values) in FIELD_NAMES.iter().zip(fields) {
+        writeln!(out, "SCALARS {name} double 1")?;
+        writeln!(out, "LOOKUP_TABLE default")?;
+        for v in values {
+            writeln!(out, "{v}")?;
+        }
+    }
+
+    writeln!(out, "VECTORS velocity double")?;
+    for (vx, vy) in fields[1].iter().zip(&fields[2]) {
+        writeln!(out, "{vx} {vy} 0")?;
+    }
+    Ok(())
+}
+
+/// Writes a row-major `height x width` array of little-endian doubles in the `.npy` format.
+pub fn write_npy<W: Write>(out: &mut W, width: usize, height: usize, values: &[f64]) -> io::Result<()> {
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({height}, {width}), }}");
+    // Magic, version and header length take 10 bytes, the whole header is padded to 64
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    out.write_all(b"\x93NUMPY\x01\x00")?;
+    out.write_all(&(header.len() as u16).to_le_bytes())?;
+    out.write_all(header.as_bytes())?;
+    for v in values {
+        out.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes a CSV table with the cell coordinates followed by every field.
+pub fn write_csv<W: Write>(out: &mut W, width: usize, height: usize, fields: &[Vec<f64>; 5]) -> io::Result<()> {
+    writeln!(out, "x,y,{}", FIELD_NAMES.join(","))?;
+    for y in 0..height {
+        for x in 0..width {
+            let idx = x + y * width;
+            write!(out, "{x},{y}")?;
+            for values in fields {
+                write!(out, ",{}", values[idx])?;
+            }
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the simulation fields of every `stride`-th captured step into a directory.
+///
+/// Each export is numbered, and `times.csv` maps the numbers to simulation time.
+pub struct FieldExporter {
+    pub path: PathBuf,
+    pub format: FieldFormat,
+    pub stride: usize,
+    step: usize,
+    pub written: usize,
+    times: BufWriter<File>,
+}
+
+impl FieldExporter {
+    pub fn start(path: PathBuf, format: FieldFormat, stride: usize) -> io::Result<Self> {
+        std::fs::create_dir_all(&path)?;
+        let mut times = BufWriter::new(File::create(path.join("times.csv"))?);
+        writeln!(times, "index,time")?;
+
+        Ok(FieldExporter {
+            path,
+            format,
+            stride: stride.max(1),
+            step: 0,
+            written: 0,
+            times,
+        })
+    }
+
+    /// Offers a simulated step, written if it falls on the stride.
+    pub fn capture(&mut self, fluid: &FluidSim, time: f64) -> io::Result<()> {
+        let step = self.step;
+        self.step += 1;
+        if !step.is_multiple_of(self.stride) {
+            return Ok(());
+        }
+
+        write_fields(&self.path, self.written, self.format, fluid, time)?;
+        writeln!(self.times, "{},{time}", self.written)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.times.flush()
+    }
+}
+
+/// Writes one step of the fields into `dir`, named after `index`.
+pub fn write_fields(dir: &Path, index: usize, format: FieldFormat, fluid: &FluidSim, time: f64) -> io::Result<()> {
+    let fields = sample_fields(fluid);
+    let (width, height) = (fluid.width, fluid.height);
+
+    match format {
+        FieldFormat::Vtk => {
+            let mut out = BufWriter::new(File::create(dir.join(format!("fields_{index:06}.vtk")))?);
+            write_vtk(&mut out, width, height, time, &fields)?;
+            out.flush()
+        }
+        FieldFormat::Npy => {
+            for (name, values) in FIELD_NAMES.iter().zip(&fields) {
+                let mut out = BufWriter::new(File::create(dir.join(format!("{name}_{index:06}.npy")))?);
+                write_npy(&mut out, width, height, values)?;
+                out.flush()?;
+            }
+            Ok(())
+        }
+        FieldFormat::Csv => {
+            let mut out = BufWriter::new(File::create(dir.join(format!("fields_{index:06}.csv")))?);
+            write_csv(&mut out, width, height, &fields)?;
+            out.flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npy_header_is_padded_and_describes_a_row_major_array() {
+        let values: Vec<f64> = (0..6).map(|i| i as f64 * 0.5).collect();
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, 3, 2, &values).unwrap();
+
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        let data_start = 10 + header_len;
+        assert_eq!(data_start % 64, 0, "the data starts at byte {data_start}");
+
+        let header = std::str::from_utf8(&bytes[10..data_start]).unwrap();
+        assert!(header.ends_with('\n'));
+        assert_eq!(header.trim_end(), "{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }");
+
+        assert_eq!(bytes.len(), data_start + 6 * 8);
+        let data: Vec<f64> = bytes[data_start..]
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(data, values);
+    }
+
+    #[test]
+    fn vtk_lists_every_field_at_every_point() {
+        let fields: [Vec<f64>; 5] = std::array::from_fn(|field| (0..6).map(|i| (field * 10 + i) as f64).collect());
+        let mut bytes = Vec::new();
+        write_vtk(&mut bytes, 3, 2, 1.5, &fields).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[..11], [
+            "# vtk DataFile Version 3.0",
+            "fluid-sim fields",
+            "ASCII",
+            "DATASET STRUCTURED_POINTS",
+            "DIMENSIONS 3 2 1",
+            "ORIGIN 0 0 0",
+            "SPACING 1 1 1",
+            "FIELD FieldData 1",
+            "TIME 1 1 double",
+            "1.5",
+            "POINT_DATA 6",
+        ]);
+
+        // Each scalar takes a header, a lookup table line and a value per point
+        for (i, name) in FIELD_NAMES.iter().enumerate() {
+            let start = 11 + i * 8;
+            assert_eq!(lines[start], format!("SCALARS {name} double 1"));
+            assert_eq!(lines[start + 1], "LOOKUP_TABLE default");
+            let values: Vec<f64> = lines[start + 2..start + 8].iter().map(|v| v.parse().unwrap()).collect();
+            assert_eq!(values, fields[i]);
+        }
+
+        let vectors = 11 + 5 * 8;
+        assert_eq!(lines[vectors], "VECTORS velocity double");
+        assert_eq!(lines[vectors + 1], "10 20 0");
+        assert_eq!(lines.len(), vectors + 1 + 6);
+    }
+}
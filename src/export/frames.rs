@@ -0,0 +1,188 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::y4m::Y4mWriter;
+
+/// Frame rate written in animated outputs, one recorded frame per tick.
+pub const RECORD_FPS: u32 = 30;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+pub enum RecordFormat {
+    /// Numbered PNG files in a directory
+    Png,
+    /// A looping animated GIF
+    Gif,
+    /// A raw YUV4MPEG2 video stream
+    Y4m,
+}
+
+impl RecordFormat {
+    pub const ALL: [RecordFormat; 3] = [RecordFormat::Png, RecordFormat::Gif, RecordFormat::Y4m];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordFormat::Png => "PNG Sequence",
+            RecordFormat::Gif => "Animated GIF",
+            RecordFormat::Y4m => "Y4M Video",
+        }
+    }
+
+    /// File extension of the output, `None` when frames go to a directory.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            RecordFormat::Png => None,
+            RecordFormat::Gif => Some("gif"),
+            RecordFormat::Y4m => Some("y4m"),
+        }
+    }
+}
+
+enum Output {
+    Png,
+    Gif(gif::Encoder<BufWriter<File>>),
+    Y4m(Y4mWriter<BufWriter<File>>),
+}
+
+/// Writes every `stride`-th captured frame, rescaled by `scale`, in the chosen format.
+///
+/// Animated outputs are opened on the first frame since they need its size.
+pub struct FrameRecorder {
+    pub path: PathBuf,
+    pub format: RecordFormat,
+    pub stride: usize,
+    pub scale: f64,
+    frame: usize,
+    pub written: usize,
+    size: Option<(usize, usize)>,
+    output: Option<Output>,
+}
+
+impl FrameRecorder {
+    pub fn start(path: PathBuf, format: RecordFormat, stride: usize, scale: f64) -> io::Result<Self> {
+        match format {
+            RecordFormat::Png => std::fs::create_dir_all(&path)?,
+            RecordFormat::Gif | RecordFormat::Y4m => {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+            }
+        }
+
+        Ok(FrameRecorder {
+            path,
+            format,
+            stride: stride.max(1),
+            scale: scale.clamp(0.05, 4.0),
+            frame: 0,
+            written: 0,
+            size: None,
+            output: None,
+        })
+    }
+
+    /// Counts a frame and writes it out if it falls on the stride.
+    pub fn capture(&mut self, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+        let keep = self.frame.is_multiple_of(self.stride);
+        self.frame += 1;
+        if !keep {
+            return Ok(());
+        }
+
+        let (width, height, pixels) = rescale(width, height, pixels, self.scale);
+
+        match self.size {
+            None => self.size = Some((width, height)),
+            Some(size) if size != (width, height) => {
+                return Err(io::Error::other("the frame size changed during the recording"));
+            }
+            Some(_) => {}
+        }
+
+        let output = match &mut self.output {
+            Some(output) => output,
+            None => self.output.insert(open_output(&self.path, self.format, width, height)?),
+        };
+
+        match output {
+            Output::Png => {
+                let path = self.path.join(format!("frame_{:06}.png", self.written));
+                write_png(&path, width, height, &pixels)?;
+            }
+            Output::Gif(encoder) => {
+                let mut frame = gif::Frame::from_rgb_speed(width as u16, height as u16, &super::to_rgb(&pixels), 10);
+                // Delays are in hundredths of a second, rounding the time of each frame rather than
+                // its delay keeps the average at RECORD_FPS, 30 fps giving delays of 3, 4, 3, ...
+                let centiseconds = |frames: usize| (frames as u64 * 100 + RECORD_FPS as u64 / 2) / RECORD_FPS as u64;
+                frame.delay = (centiseconds(self.written + 1) - centiseconds(self.written)) as u16;
+                encoder.write_frame(&frame).map_err(io::Error::other)?;
+            }
+            Output::Y4m(writer) => writer.write_frame(&pixels)?,
+        }
+
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Flushes and closes the output.
+    pub fn finish(self) -> io::Result<()> {
+        match self.output {
+            // Dropping the encoder writes the GIF trailer
+            Some(Output::Gif(encoder)) => encoder.into_inner().map(|_| ()),
+            Some(Output::Y4m(mut writer)) => writer.flush(),
+            Some(Output::Png) | None => Ok(()),
+        }
+    }
+}
+
+fn open_output(path: &Path, format: RecordFormat, width: usize, height: usize) -> io::Result<Output> {
+    Ok(match format {
+        RecordFormat::Png => Output::Png,
+        RecordFormat::Gif => {
+            if width > u16::MAX as usize || height > u16::MAX as usize {
+                return Err(io::Error::other("frames are too large for a GIF"));
+            }
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[]).map_err(io::Error::other)?;
+            encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+            Output::Gif(encoder)
+        }
+        RecordFormat::Y4m => Output::Y4m(Y4mWriter::new(BufWriter::new(File::create(path)?), width, height, RECORD_FPS)?),
+    })
+}
+
+/// Nearest neighbour resampling of a frame by `scale`.
+fn rescale(width: usize, height: usize, pixels: &[u32], scale: f64) -> (usize, usize, Vec<u32>) {
+    if scale == 1.0 {
+        return (width, height, pixels.to_vec());
+    }
+
+    let out_width = ((width as f64 * scale).round() as usize).max(1);
+    let out_height = ((height as f64 * scale).round() as usize).max(1);
+    let mut out = Vec::with_capacity(out_width * out_height);
+
+    for y in 0..out_height {
+        let sy = (y * height / out_height).min(height - 1);
+        for x in 0..out_width {
+            let sx = (x * width / out_width).min(width - 1);
+            out.push(pixels[sx + sy * width]);
+        }
+    }
+
+    (out_width, out_height, out)
+}
+
+/// Writes `0x00RRGGBB` pixels as an 8-bit RGB PNG.
+pub fn write_png(path: &Path, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&super::to_rgb(pixels)).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
@@ -0,0 +1,34 @@
+pub mod diagnostics;
+pub mod fields;
+pub mod frames;
+pub mod y4m;
+
+use std::path::{Path, PathBuf};
+
+pub use diagnostics::{DiagnosticsLog, LogFormat};
+pub use fields::{FieldExporter, FieldFormat};
+pub use frames::{FrameRecorder, RecordFormat};
+
+/// Directory where recordings and exports are written.
+pub const OUTPUT_DIR: &str = "recordings";
+
+/// A fresh, timestamped path inside the output directory, e.g. `recordings/frames_1712345678901`.
+pub fn timestamped_path(prefix: &str, extension: Option<&str>) -> PathBuf {
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let name = match extension {
+        Some(ext) => format!("{prefix}_{stamp}.{ext}"),
+        None => format!("{prefix}_{stamp}"),
+    };
+    Path::new(OUTPUT_DIR).join(name)
+}
+
+/// Unpacks `0x00RRGGBB` pixels into RGB bytes.
+pub fn to_rgb(pixels: &[u32]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|&c| [(c >> 16) as u8, (c >> 8) as u8, c as u8])
+        .collect()
+}
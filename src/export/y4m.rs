@@ -0,0 +1,40 @@
+use std::io::{self, Write};
+
+/// Minimal YUV4MPEG2 stream writer, full resolution 4:4:4 so no chroma is lost.
+///
+/// Y4M is the raw format ffmpeg and most encoders read from a pipe or file.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut writer: W, width: usize, height: usize, fps: u32) -> io::Result<Self> {
+        writeln!(writer, "YUV4MPEG2 W{width} H{height} F{fps}:1 Ip A1:1 C444")?;
+        Ok(Y4mWriter { writer, width, height })
+    }
+
+    /// Writes one frame of `0x00RRGGBB` pixels, converted with BT.601 coefficients.
+    pub fn write_frame(&mut self, pixels: &[u32]) -> io::Result<()> {
+        let size = self.width * self.height;
+        let mut planes = vec![0u8; size * 3];
+
+        for (i, &c) in pixels.iter().enumerate().take(size) {
+            let r = ((c >> 16) & 0xFF) as f64;
+            let g = ((c >> 8) & 0xFF) as f64;
+            let b = (c & 0xFF) as f64;
+
+            planes[i] = (16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8;
+            planes[size + i] = (128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8;
+            planes[2 * size + i] = (128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8;
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&planes)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
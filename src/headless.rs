@@ -0,0 +1,91 @@
+use std::io;
+use std::path::PathBuf;
+
+use crate::input::FrameInput;
+use crate::settings::SimulationSettings;
+use crate::window::{self, FluidWindow};
+
+/// Number of steps of a headless run that isn't replaying input.
+pub const DEFAULT_STEPS: usize = 300;
+
+/// What a headless run does on top of the simulation settings.
+pub struct HeadlessOptions {
+    pub steps: Option<usize>,
+    pub dt: f64,
+    pub scenario: Option<PathBuf>,
+    pub snapshot: Option<PathBuf>,
+    /// Input recording to replay, its time step replaces `dt`.
+    pub replay: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    /// Directory of a field time series. Like `diagnostics`, it replaces the recording
+    /// of frames unless `output` is set.
+    pub fields: Option<PathBuf>,
+    /// Diagnostics log appended to at every step.
+    pub diagnostics: Option<PathBuf>,
+}
+
+/// Runs the simulation without a window at a fixed time step, recording the frames
+/// and exporting the fields in the configured formats.
+pub fn run(settings: &SimulationSettings, options: &HeadlessOptions) -> io::Result<()> {
+    let mut window = FluidWindow::new(settings);
+    if let Some(path) = &options.scenario {
+        window.load_scenario(path)?;
+    }
+    if let Some(path) = &options.snapshot {
+        window.load_snapshot(path)?;
+    }
+    if let Some(path) = &options.replay {
+        window.start_replay(path)?;
+    }
+    let steps = match options.steps {
+        Some(steps) => steps,
+        None if options.replay.is_some() => window.replay_remaining() as usize,
+        None => match window.scenario().and_then(|s| s.duration) {
+            Some(duration) => (duration / options.dt.min(window::MAX_DT)).ceil() as usize,
+            None => DEFAULT_STEPS,
+        },
+    };
+
+    let recording = options.output.is_some() || (options.fields.is_none() && options.diagnostics.is_none());
+    if recording {
+        window.start_recording(options.output.clone())?;
+    }
+    if let Some(path) = &options.fields {
+        window.start_field_export(Some(path.clone()))?;
+    }
+    if let Some(path) = &options.diagnostics {
+        window.start_diagnostics(Some(path.clone()))?;
+    }
+
+    // The mass budget of the run is printed at the end
+    window.measure = true;
+    window.draw = recording;
+    let input = FrameInput::default();
+    let start_mass = window.fluid.total_mass();
+    let (mut sources, mut outflow, mut drift, mut correction) = (0.0, 0.0, 0.0, 0.0);
+    for step in 0..steps {
+        window.frame(&input, options.dt);
+        let mass = window.fluid.mass;
+        sources += mass.sources;
+        outflow += mass.outflow;
+        drift += mass.drift;
+        correction += mass.correction;
+        if recording != window.is_recording() || options.fields.is_some() != window.is_exporting() ||
+            options.diagnostics.is_some() != window.is_logging() {
+            return Err(io::Error::other(format!("export stopped at step {step}: {}", window.status())));
+        }
+    }
+
+    if let Some((written, path)) = window.stop_recording()? {
+        println!("Wrote {written} frames to {}", path.display());
+    }
+    if let Some((written, path)) = window.stop_field_export()? {
+        println!("Exported {written} steps to {}", path.display());
+    }
+    if let Some((written, path)) = window.stop_diagnostics()? {
+        println!("Logged {written} steps to {}", path.display());
+    }
+    println!("Mass {start_mass:.3} -> {:.3}: sources {sources:+.3}, outflow {outflow:+.3}, drift {drift:+.3}, correction {correction:+.3}",
+        window.fluid.total_mass());
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::brush::Brush;
+
+/// Keys the simulation view reacts to, independent of the GUI toolkit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Key {
+    Space,
+    Right,
+    Up,
+    Home,
+    Backspace,
+    Delete,
+    F5,
+    F9,
+    B,
+    C,
+    D,
+    E,
+    F,
+    H,
+    I,
+    L,
+    M,
+    N,
+    O,
+    P,
+    R,
+    T,
+    V,
+    X,
+    Y,
+    Z,
+}
+
+/// Everything the simulation view needs to know about the user input of one frame.
+#[derive(Clone, Default, Debug)]
+pub struct FrameInput {
+    /// Cursor position in view pixels, `None` when the cursor is outside the view.
+    pub mouse: Option<(f64, f64)>,
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+    /// Wheel movement, positive when scrolling up.
+    pub scroll: f64,
+    /// Keys pressed this frame (not held).
+    pub pressed: Vec<Key>,
+}
+
+/// The brush input of one frame in grid space, as painted and as stored in input recordings.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Stroke {
+    /// Cursor position in grid cells.
+    pub x: f64,
+    pub y: f64,
+    /// Cursor position of the previous frame, the stroke paints the whole segment from it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<(f64, f64)>,
+    /// Brush radius in cells along each axis.
+    pub radius: (f64, f64),
+    /// Whether density is being added.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub density: bool,
+    /// Velocity impulse in cells per second when the fluid is being pushed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impulse: Option<(f64, f64)>,
+    #[serde(default)]
+    pub brush: Brush,
+}
@@ -1,58 +1,395 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
 use eframe::egui::{self, Color32, ComboBox};
 
+mod cli;
+mod export;
+mod headless;
+mod input;
+mod render;
+mod replay;
+mod settings;
+mod view;
 mod window;
 mod simulation;
 
-fn launch_simulation(width: usize, height: usize, particle_radius: usize, precision: usize, start_density: f64, diffusion_rate: f64, 
-    max_color: u32, randomize: bool, random_smoothing: usize, pressure_iters: usize, diffusion_iters: usize) {
+use export::{FieldFormat, LogFormat, RecordFormat};
+use input::{FrameInput, Key};
+use render::{ColormapKind, RenderField};
+use settings::{SettingsFile, SimulationSettings};
+use simulation::brush::BrushShape;
+
+/// Width of the settings panel next to the simulation view, in points.
+const SETTINGS_PANEL_WIDTH: f32 = 340.0;
 
-    let mut window = window::FluidWindow::new(width, height, particle_radius, precision, start_density, diffusion_rate,
-         max_color, randomize, random_smoothing, pressure_iters, diffusion_iters);
-    window.run();
+const KEY_BINDINGS: [(egui::Key, Key); 26] = [
+    (egui::Key::Space, Key::Space),
+    (egui::Key::ArrowRight, Key::Right),
+    (egui::Key::ArrowUp, Key::Up),
+    (egui::Key::Home, Key::Home),
+    (egui::Key::Backspace, Key::Backspace),
+    (egui::Key::Delete, Key::Delete),
+    (egui::Key::F5, Key::F5),
+    (egui::Key::F9, Key::F9),
+    (egui::Key::B, Key::B),
+    (egui::Key::C, Key::C),
+    (egui::Key::D, Key::D),
+    (egui::Key::E, Key::E),
+    (egui::Key::F, Key::F),
+    (egui::Key::H, Key::H),
+    (egui::Key::I, Key::I),
+    (egui::Key::L, Key::L),
+    (egui::Key::M, Key::M),
+    (egui::Key::N, Key::N),
+    (egui::Key::O, Key::O),
+    (egui::Key::P, Key::P),
+    (egui::Key::R, Key::R),
+    (egui::Key::T, Key::T),
+    (egui::Key::V, Key::V),
+    (egui::Key::X, Key::X),
+    (egui::Key::Y, Key::Y),
+    (egui::Key::Z, Key::Z),
+];
+
+/// A simulation shown in the central panel, with the texture it is uploaded to.
+struct RunningSimulation {
+    window: window::FluidWindow,
+    texture: Option<egui::TextureHandle>,
+    applied_settings: SimulationSettings,
+    last_frame: std::time::Instant,
 }
 
-struct SimulationSettings {
-    width: usize,
-    height: usize,
-    particle_radius: usize,
-    precision: usize,
-    start_density: f64,
-    max_density_color: Color32,
-    diffusion_rate: f64,
-    randomize: bool,
-    random_smoothing: usize,
-    pressure_iters: usize,
-    diffusion_iters: usize,
+fn launch_simulation(settings: &SimulationSettings) -> RunningSimulation {
+    RunningSimulation {
+        window: window::FluidWindow::new(settings),
+        texture: None,
+        applied_settings: settings.clone(),
+        last_frame: std::time::Instant::now(),
+    }
 }
 
-impl Default for SimulationSettings {
-    fn default() -> Self {
-        Self {
-            width: 800,
-            height: 600,
-            particle_radius: 10,
-            precision: 10,
-            start_density: 0.2,
-            max_density_color: Color32::WHITE,
-            diffusion_rate: 0.1,
-            randomize: false,
-            random_smoothing: 100,
-            pressure_iters: 3,
-            diffusion_iters: 3,
+impl RunningSimulation {
+    /// Takes what the simulation changed on its own, through hotkeys or loaded files, into
+    /// the panel settings, so applying the next edit of the panel doesn't revert it.
+    fn sync_settings(&mut self, settings: &mut SimulationSettings) {
+        self.window.store_live_settings(settings);
+        if self.applied_settings != *settings {
+            self.applied_settings = settings.clone();
+        }
+    }
+}
+
+/// Collects the input of this frame that concerns the simulation view.
+fn frame_input(ctx: &egui::Context, response: &egui::Response) -> FrameInput {
+    let mut input = FrameInput::default();
+
+    if !ctx.wants_keyboard_input() {
+        ctx.input(|i| {
+            for (egui_key, key) in KEY_BINDINGS {
+                if i.key_pressed(egui_key) {
+                    input.pressed.push(key);
+                }
+            }
+        });
+    }
+
+    // Keep following the cursor outside the view while a drag started inside it
+    if response.hovered() || response.dragged() {
+        ctx.input(|i| {
+            if let Some(pos) = i.pointer.latest_pos() {
+                let pos = pos - response.rect.min;
+                input.mouse = Some((pos.x as f64, pos.y as f64));
+            }
+            input.left = i.pointer.button_down(egui::PointerButton::Primary);
+            input.right = i.pointer.button_down(egui::PointerButton::Secondary);
+            input.middle = i.pointer.button_down(egui::PointerButton::Middle);
+            if response.hovered() {
+                input.scroll = i.raw_scroll_delta.y as f64;
+            }
+        });
+    }
+
+    input
+}
+
+fn to_color_image(width: usize, height: usize, buffer: &[u32]) -> egui::ColorImage {
+    egui::ColorImage {
+        size: [width, height],
+        pixels: buffer
+            .iter()
+            .map(|&c| Color32::from_rgb((c >> 16) as u8, (c >> 8) as u8, c as u8))
+            .collect(),
+    }
+}
+
+/// Files from the command line the first simulation starts from.
+struct StartFiles {
+    scenario: Option<PathBuf>,
+    snapshot: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    diagnostics: Option<PathBuf>,
+}
+
+impl StartFiles {
+    /// Loads the files the simulation starts from and opens the diagnostics log.
+    fn load(&self, window: &mut window::FluidWindow) -> Result<(), (PathBuf, std::io::Error)> {
+        if let Some(path) = &self.scenario {
+            window.load_scenario(path).map_err(|e| (path.clone(), e))?;
+        }
+        if let Some(path) = &self.snapshot {
+            window.load_snapshot(path).map_err(|e| (path.clone(), e))?;
+        }
+        if let Some(path) = &self.replay {
+            window.start_replay(path).map_err(|e| (path.clone(), e))?;
+        }
+        if let Some(path) = &self.diagnostics {
+            window.start_diagnostics(Some(path.clone())).map_err(|e| (path.clone(), e))?;
         }
+        Ok(())
     }
 }
 
 struct MyApp {
     settings: SimulationSettings,
     dark_theme_set: bool,
+    running: Option<RunningSimulation>,
+    show_launcher: bool,
+    settings_file: SettingsFile,
+    preset_name: String,
+    settings_error: Option<String>,
+    scenario_path: String,
 }
 
-impl Default for MyApp {
-    fn default() -> Self {
+impl MyApp {
+    fn new(mut settings: SimulationSettings, settings_file: SettingsFile, settings_error: Option<String>, show_launcher: bool,
+        start: StartFiles) -> Self {
+
+        let launch = !show_launcher || start.scenario.is_some() || start.snapshot.is_some() || start.replay.is_some() ||
+            start.diagnostics.is_some();
+        let mut running = launch.then(|| launch_simulation(&settings));
+        if let Some(sim) = &mut running {
+            if let Err((path, e)) = start.load(&mut sim.window) {
+                eprintln!("error: unable to load {}: {e}", path.display());
+                std::process::exit(1);
+            }
+            sim.sync_settings(&mut settings);
+        }
+
         Self {
-            settings: SimulationSettings::default(),
+            running,
+            settings,
             dark_theme_set: false,
+            show_launcher,
+            settings_file,
+            preset_name: String::new(),
+            settings_error,
+            scenario_path: start.scenario.map(|path| path.display().to_string()).unwrap_or_default(),
+        }
+    }
+
+    fn save_settings_file(&mut self) {
+        self.settings_file.current = self.settings.clone();
+        self.settings_error = self
+            .settings_file
+            .save(Path::new(SettingsFile::PATH))
+            .err()
+            .map(|e| format!("Unable to write {}: {e}", SettingsFile::PATH));
+    }
+
+    fn presets_ui(&mut self, ui: &mut egui::Ui) {
+        let running = self.running.is_some();
+
+        ui.horizontal(|ui| {
+            ComboBox::from_label("Preset")
+                .selected_text(if self.preset_name.is_empty() { "Select..." } else { self.preset_name.as_str() })
+                .show_ui(ui, |ui| {
+                    for (name, preset) in &self.settings_file.presets {
+                        if ui.selectable_label(*name == self.preset_name, name).clicked() {
+                            self.preset_name = name.clone();
+                            let mut preset = preset.clone();
+                            if running {
+                                // The grid can't change under a running simulation, keep its launch settings
+                                preset.width = self.settings.width;
+                                preset.height = self.settings.height;
+                                preset.precision = self.settings.precision;
+                            }
+                            self.settings = preset;
+                        }
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.preset_name).hint_text("Preset name").desired_width(140.0));
+
+            let name = self.preset_name.trim().to_string();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Save")).clicked() {
+                self.settings_file.presets.insert(name.clone(), self.settings.clone());
+                self.save_settings_file();
+            }
+            if ui.add_enabled(self.settings_file.presets.contains_key(&name), egui::Button::new("Delete")).clicked() {
+                self.settings_file.presets.remove(&name);
+                self.save_settings_file();
+            }
+            if ui.button("Reset").clicked() {
+                self.settings = SimulationSettings::default();
+            }
+        });
+
+        if let Some(error) = &self.settings_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+    }
+
+    fn settings_ui(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let running = self.running.is_some();
+
+        ui.heading("Fluid Simulation Settings");
+
+        self.presets_ui(ui);
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Scenario");
+            ui.text_edit_singleline(&mut self.scenario_path);
+            if ui.add_enabled(running, egui::Button::new("Load")).on_disabled_hover_text("Launch a simulation first").clicked() {
+                let path = PathBuf::from(&self.scenario_path);
+                if let Some(sim) = &mut self.running {
+                    self.settings_error = sim.window.load_scenario(&path)
+                        .err()
+                        .map(|e| format!("Unable to load {}: {e}", path.display()));
+                    // The scenario's solver settings are part of the experiment, the panel takes them over
+                    sim.sync_settings(&mut self.settings);
+                }
+            }
+        });
+        ui.separator();
+
+        ui.add_enabled_ui(!running, |ui| {
+            ui.add(egui::Slider::new(&mut self.settings.width, 100..=1920).text("Width"));
+            ui.add(egui::Slider::new(&mut self.settings.height, 100..=1080).text("Height"));
+            ui.add(egui::Slider::new(&mut self.settings.precision, 1..=20).text("Precision (pixels per cell)"));
+
+            ui.add(egui::Slider::new(&mut self.settings.start_density, 0.0..=1.0).text("Default Density"));
+            ui.checkbox(&mut self.settings.randomize, "Randomize Initial Density (it overrides Default Density)");
+            ui.add(egui::Slider::new(&mut self.settings.random_smoothing, 1..=10000).text("Random Smoothing"));
+        });
+
+        ui.separator();
+        if running {
+            ui.label("Live parameters, applied to the running simulation");
+        }
+
+        ui.add(egui::Slider::new(&mut self.settings.diffusion_rate, 0.0..=5.0).text("Diffusion Rate"));
+
+        ui.add(egui::Slider::new(&mut self.settings.pressure_iters, 0..=10).text("Pressure Iterations"));
+        ui.add(egui::Slider::new(&mut self.settings.diffusion_iters, 0..=10).text("Diffusion Iterations"));
+        ui.checkbox(&mut self.settings.conserve_mass, "Conserve Mass (correct the dye lost or gained by the solver)");
+
+        ui.add(egui::Slider::new(&mut self.settings.particle_radius, 1..=50).text("Mouse Radius (pixels)"));
+        ComboBox::from_label("Brush Shape")
+            .selected_text(self.settings.brush_shape.name())
+            .show_ui(ui, |ui| {
+                for shape in BrushShape::ALL {
+                    ui.selectable_value(&mut self.settings.brush_shape, shape, shape.name());
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.settings.brush_strength, 0.0..=10.0).text("Brush Density per Second"));
+        ui.add(egui::Slider::new(&mut self.settings.brush_force, 0.0..=0.5).text("Brush Force"));
+        ui.checkbox(&mut self.settings.eraser, "Eraser, left click removes density (Z)");
+
+        ui.separator();
+        ui.label("Emitters (N) and sinks (M) at the cursor, Delete removes them");
+        ui.add(egui::Slider::new(&mut self.settings.emitter_density, 0.0..=10.0).text("Emitter Density per Second"));
+        ui.add(egui::Slider::new(&mut self.settings.emitter_speed, 0.0..=200.0).text("Emitter Speed (cells/s)"));
+        ui.add(egui::Slider::new(&mut self.settings.emitter_angle, -180.0..=180.0).text("Emitter Angle (degrees)"));
+        ui.add(egui::Slider::new(&mut self.settings.sink_rate, 0.0..=10.0).text("Sink Rate per Second"));
+        ui.separator();
+
+        ComboBox::from_label("Rendered Field")
+            .selected_text(self.settings.field.name())
+            .show_ui(ui, |ui| {
+                for field in RenderField::ALL {
+                    ui.selectable_value(&mut self.settings.field, field, field.name());
+                }
+            });
+
+        ui.checkbox(&mut self.settings.smooth, "Smooth Rendering (bilinear upscaling)");
+        ui.checkbox(&mut self.settings.lic, "Line Integral Convolution (replaces the rendered field)");
+        ui.checkbox(&mut self.settings.lic_modulate, "Modulate LIC by density");
+
+        ComboBox::from_label("Colormap")
+            .selected_text(self.settings.colormap.name())
+            .show_ui(ui, |ui| {
+                for kind in ColormapKind::ALL {
+                    ui.selectable_value(&mut self.settings.colormap, kind, kind.name());
+                }
+            });
+
+        if self.settings.colormap == ColormapKind::Custom {
+            ui.label("Gradient Colors (low to high)");
+            ui.horizontal_wrapped(|ui| {
+                for color in self.settings.gradient.iter_mut() {
+                    ui.color_edit_button_srgba(color);
+                }
+                if ui.button("+").clicked() {
+                    let last = self.settings.gradient.last().copied().unwrap_or(Color32::WHITE);
+                    self.settings.gradient.push(last);
+                }
+                if self.settings.gradient.len() > 2 && ui.button("-").clicked() {
+                    self.settings.gradient.pop();
+                }
+            });
+        }
+
+        ui.separator();
+        ui.label("Recording (R to start/stop)");
+        ComboBox::from_label("Format")
+            .selected_text(self.settings.record_format.name())
+            .show_ui(ui, |ui| {
+                for format in RecordFormat::ALL {
+                    ui.selectable_value(&mut self.settings.record_format, format, format.name());
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.settings.record_stride, 1..=60).text("Record Every N Steps"));
+        ui.add(egui::Slider::new(&mut self.settings.record_scale, 0.1..=2.0).text("Frame Scale"));
+        ui.checkbox(&mut self.settings.record_grid, "Record the field at grid resolution");
+
+        ui.separator();
+        ui.label("Field Export (E for one step, X to start/stop a series)");
+        ComboBox::from_label("Field Format")
+            .selected_text(self.settings.field_format.name())
+            .show_ui(ui, |ui| {
+                for format in FieldFormat::ALL {
+                    ui.selectable_value(&mut self.settings.field_format, format, format.name());
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.settings.field_stride, 1..=60).text("Export Every N Steps"));
+
+        ui.separator();
+        ui.label("Diagnostics Log (D to start/stop)");
+        ComboBox::from_label("Log Format")
+            .selected_text(self.settings.log_format.name())
+            .show_ui(ui, |ui| {
+                for format in LogFormat::ALL {
+                    ui.selectable_value(&mut self.settings.log_format, format, format.name());
+                }
+            });
+
+        ui.separator();
+        if running {
+            if ui.button("Stop Simulation").clicked() {
+                self.running = None;
+            }
+        } else if ui.button("Launch Simulation").clicked() {
+            self.running = Some(launch_simulation(&self.settings));
+            self.save_settings_file();
+            // Grow the app so the view gets the requested size, it follows later resizes
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(
+                self.settings.width as f32 + SETTINGS_PANEL_WIDTH,
+                self.settings.height as f32 + 24.0,
+            )));
         }
     }
 }
@@ -65,51 +402,147 @@ impl eframe::App for MyApp {
             self.dark_theme_set = true;
         }
 
+        if self.show_launcher {
+            egui::SidePanel::left("settings")
+                .resizable(false)
+                .exact_width(SETTINGS_PANEL_WIDTH)
+                .show(ctx, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| self.settings_ui(ctx, ui));
+                });
+        }
+
+        if let Some(sim) = &self.running {
+            // A single truncated line, so notifications and counters never change the size of the view
+            egui::TopBottomPanel::top("status").show(ctx, |ui| {
+                ui.add(egui::Label::new(sim.window.status()).truncate());
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("Fluid Simulation Settings");
+            let Some(sim) = &mut self.running else {
+                ui.centered_and_justified(|ui| ui.label("Launch a simulation from the settings panel"));
+                return;
+            };
 
-            ui.add(egui::Slider::new(&mut self.settings.width, 100..=1920).text("Width"));
-            ui.add(egui::Slider::new(&mut self.settings.height, 100..=1080).text("Height"));
+            if sim.applied_settings != self.settings {
+                sim.window.apply_live_settings(&self.settings);
+                sim.applied_settings = self.settings.clone();
+            }
 
-            ui.add(egui::Slider::new(&mut self.settings.diffusion_rate, 0.0..=5.0).text("Diffusion Rate"));
+            let size = ui.available_size();
+            sim.window.resize(size.x as usize, size.y as usize);
 
-            ui.add(egui::Slider::new(&mut self.settings.pressure_iters, 0..=10).text("Pressure Iterations"));
-            ui.add(egui::Slider::new(&mut self.settings.diffusion_iters, 0..=10).text("Diffusion Iterations"));
+            let (rect, response) = ui.allocate_exact_size(
+                egui::vec2(sim.window.width as f32, sim.window.height as f32),
+                egui::Sense::click_and_drag(),
+            );
 
-            ui.add(egui::Slider::new(&mut self.settings.particle_radius, 1..=50).text("Mouse Radius (pixels)"));
+            // Take the keyboard focus so hotkeys don't also press a focused button
+            if response.hovered() {
+                response.request_focus();
+            }
 
-            ComboBox::from_label("Precision (pixels)")
-                .selected_text(format!("{}", self.settings.precision))
-                .show_ui(ui, |ui| {
-                    for level in [1, 2, 5, 10] {
-                        ui.selectable_value(&mut self.settings.precision, level, format!("{level}"));
-                    }
-                });
+            let input = frame_input(ctx, &response);
+            let now = std::time::Instant::now();
+            let dt = (now - sim.last_frame).as_secs_f64();
+            sim.last_frame = now;
+            sim.window.frame(&input, dt);
+            sim.sync_settings(&mut self.settings);
 
-            ui.add(egui::Slider::new(&mut self.settings.start_density, 0.0..=1.0).text("Default Density"));
-            ui.checkbox(&mut self.settings.randomize, "Randomize Initial Density (it overrides Default Density)");
-            ui.add(egui::Slider::new(&mut self.settings.random_smoothing, 1..=10000).text("Random Smoothing"));
+            let image = to_color_image(sim.window.width, sim.window.height, sim.window.buffer());
+            let texture = sim.texture.get_or_insert_with(|| {
+                ctx.load_texture("fluid", image.clone(), egui::TextureOptions::NEAREST)
+            });
+            texture.set(image, egui::TextureOptions::NEAREST);
 
-            ui.label("Max Density Color");
-            ui.color_edit_button_srgba(&mut self.settings.max_density_color);
+            ui.painter().image(
+                texture.id(),
+                rect,
+                egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                Color32::WHITE,
+            );
 
-            if ui.button("Launch Simulation").clicked() {
-                let color = self.settings.max_density_color;
-                let max_color = ((color.r() as u32) << 16) | ((color.g() as u32) << 8) | ((color.b() as u32) << 0);
-                launch_simulation(self.settings.width, self.settings.height, self.settings.particle_radius, self.settings.precision, 
-                    self.settings.start_density, self.settings.diffusion_rate, max_color, self.settings.randomize, self.settings.random_smoothing, 
-                    self.settings.pressure_iters, self.settings.diffusion_iters
-                );
+            // Painted over the texture rather than into the buffer, so recorded frames stay clean
+            if sim.window.show_stats {
+                let painter = ui.painter_at(rect);
+                let margin = egui::vec2(4.0, 4.0);
+                let text = sim.window.stats_lines().join("\n");
+                let galley = painter.layout_no_wrap(text, egui::FontId::monospace(12.0), Color32::WHITE);
+                let backdrop = egui::Rect::from_min_size(rect.min + margin, galley.size() + 2.0 * margin);
+                painter.rect_filled(backdrop, 0.0, Color32::from_black_alpha(128));
+                painter.galley(backdrop.min + margin, galley, Color32::WHITE);
             }
+
+            ctx.request_repaint();
         });
     }
 }
 
 fn main() -> eframe::Result<()> {
-    let options = eframe::NativeOptions::default();
+    let cli = cli::Cli::parse();
+
+    let (settings_file, settings_error) = SettingsFile::load_or_backup(Path::new(SettingsFile::PATH));
+
+    let mut settings = match cli.settings(&settings_file) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        }
+    };
+
+    // Scenarios bring their own grid, the window starts sized to show it at the chosen precision
+    // and keeps that grid whatever size the view takes
+    if let Some(path) = &cli.scenario {
+        match simulation::scenario::Scenario::load(path) {
+            Ok(scenario) => {
+                settings.width = scenario.grid.0 * settings.precision;
+                settings.height = scenario.grid.1 * settings.precision;
+            }
+            Err(e) => {
+                eprintln!("error: unable to load {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if cli.headless {
+        let options = headless::HeadlessOptions {
+            steps: cli.steps,
+            dt: cli.dt,
+            scenario: cli.scenario,
+            snapshot: cli.snapshot,
+            replay: cli.replay,
+            output: cli.output,
+            fields: cli.export_fields,
+            diagnostics: cli.diagnostics,
+        };
+        if let Err(e) = headless::run(&settings, &options) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let start = StartFiles {
+        scenario: cli.scenario,
+        snapshot: cli.snapshot,
+        replay: cli.replay,
+        diagnostics: cli.diagnostics,
+    };
+
+    let mut options = eframe::NativeOptions::default();
+    if cli.no_launcher {
+        if let Some(error) = &settings_error {
+            eprintln!("warning: {error}");
+        }
+        options.viewport = options.viewport.with_inner_size([settings.width as f32, settings.height as f32 + 24.0]);
+    }
+
+    let show_launcher = !cli.no_launcher;
     eframe::run_native(
-        "Fluid Simulation Config",
+        "Fluid Simulation",
         options,
-        Box::new(|_cc| Ok(Box::new(MyApp::default()))),
+        Box::new(move |_cc| Ok(Box::new(MyApp::new(settings, settings_file, settings_error, show_launcher, start)))),
     )
-}
\ No newline at end of file
+}
@@ -0,0 +1,99 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+const LUT_SIZE: usize = 256;
+
+const VIRIDIS: [u32; 9] = [
+    0x440154, 0x482878, 0x3e4989, 0x31688e, 0x26828e, 0x1f9e89, 0x35b779, 0x6ece58, 0xfde725,
+];
+
+const INFERNO: [u32; 9] = [
+    0x000004, 0x1b0c41, 0x4a0c6b, 0x781c6d, 0xa52c60, 0xcf4446, 0xed6925, 0xfb9b06, 0xfcffa4,
+];
+
+const BLUE_RED: [u32; 7] = [
+    0x2166ac, 0x67a9cf, 0xd1e5f0, 0xf7f7f7, 0xfddbc7, 0xef8a62, 0xb2182b,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+pub enum ColormapKind {
+    Custom,
+    Viridis,
+    Inferno,
+    BlueRed,
+}
+
+impl ColormapKind {
+    pub const ALL: [ColormapKind; 4] = [
+        ColormapKind::Custom,
+        ColormapKind::Viridis,
+        ColormapKind::Inferno,
+        ColormapKind::BlueRed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColormapKind::Custom => "Custom Gradient",
+            ColormapKind::Viridis => "Viridis",
+            ColormapKind::Inferno => "Inferno",
+            ColormapKind::BlueRed => "Blue-Red",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|k| k == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+/// A colormap baked into a lookup table of `0x00RRGGBB` colors.
+pub struct Colormap {
+    lut: Vec<u32>,
+}
+
+impl Colormap {
+    pub fn new(kind: ColormapKind, custom: &[u32]) -> Self {
+        match kind {
+            ColormapKind::Custom => Self::from_stops(custom),
+            ColormapKind::Viridis => Self::from_stops(&VIRIDIS),
+            ColormapKind::Inferno => Self::from_stops(&INFERNO),
+            ColormapKind::BlueRed => Self::from_stops(&BLUE_RED),
+        }
+    }
+
+    /// Builds a colormap from evenly spaced color stops.
+    pub fn from_stops(stops: &[u32]) -> Self {
+        let lut = (0..LUT_SIZE)
+            .map(|i| {
+                let t = i as f64 / (LUT_SIZE - 1) as f64;
+                match stops.len() {
+                    0 => 0,
+                    1 => stops[0],
+                    n => {
+                        let pos = t * (n - 1) as f64;
+                        let i0 = (pos.floor() as usize).min(n - 2);
+                        lerp_color(stops[i0], stops[i0 + 1], pos - i0 as f64)
+                    }
+                }
+            })
+            .collect();
+
+        Colormap { lut }
+    }
+
+    /// Maps `t` in [0, 1] to a color, values outside are clamped.
+    #[inline]
+    pub fn map(&self, t: f64) -> u32 {
+        let i = (t.clamp(0.0, 1.0) * (LUT_SIZE - 1) as f64).round() as usize;
+        self.lut[i]
+    }
+}
+
+fn lerp_color(a: u32, b: u32, t: f64) -> u32 {
+    let channel = |shift: u32| {
+        let ca = ((a >> shift) & 0xFF) as f64;
+        let cb = ((b >> shift) & 0xFF) as f64;
+        ((ca + t * (cb - ca)).round() as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
@@ -0,0 +1,86 @@
+use rand::Rng;
+
+use crate::simulation::fluid_sim::FluidSim;
+use crate::view::ViewTransform;
+
+/// Line Integral Convolution of the velocity field over a white noise texture.
+///
+/// The texture lives in screen space so the streaks stay sharp regardless of
+/// the grid precision, velocities are sampled from the grid underneath.
+pub struct Lic {
+    width: usize,
+    height: usize,
+    noise: Vec<f64>,
+    /// Number of unit steps traced in each direction along the streamline.
+    pub length: usize,
+}
+
+impl Lic {
+    pub fn new(width: usize, height: usize, length: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        Lic {
+            width,
+            height,
+            noise: (0..width * height).map(|_| rng.gen_range(0.0..1.0)).collect(),
+            length,
+        }
+    }
+
+    fn noise_at(&self, x: f64, y: f64) -> Option<f64> {
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let (x, y) = (x as usize, y as usize);
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.noise[x + y * self.width])
+    }
+
+    /// Fills `out` with one LIC intensity per screen pixel, in [0, 1].
+    pub fn render(&self, fluid: &FluidSim, view: &ViewTransform, out: &mut Vec<f64>) {
+        out.clear();
+        out.resize(self.width * self.height, 0.0);
+
+        let (ppc_x, ppc_y) = view.pixels_per_cell();
+
+        for py in 0..self.height {
+            for px in 0..self.width {
+                let start = (px as f64 + 0.5, py as f64 + 0.5);
+                let mut sum = self.noise[px + py * self.width];
+                let mut count = 1.0;
+
+                for dir in [1.0, -1.0] {
+                    let (mut x, mut y) = start;
+                    for _ in 0..self.length {
+                        let (gx, gy) = view.screen_to_grid(x, y);
+                        let (vx, vy) = fluid.sample_velocity(gx, gy);
+                        // Grid cells may not be square on screen, walk in screen space
+                        let (vx, vy) = (vx * ppc_x, vy * ppc_y);
+                        let speed = vx.hypot(vy);
+                        if speed < 1e-9 {
+                            break;
+                        }
+                        x += dir * vx / speed;
+                        y += dir * vy / speed;
+
+                        match self.noise_at(x, y) {
+                            Some(n) => {
+                                sum += n;
+                                count += 1.0;
+                            }
+                            None => break,
+                        }
+                    }
+                }
+
+                out[px + py * self.width] = sum / count;
+            }
+        }
+
+        // Averaging pulls everything towards 0.5, stretch the contrast back out
+        for v in out.iter_mut() {
+            *v = (0.5 + (*v - 0.5) * 3.0).clamp(0.0, 1.0);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+pub mod colormap;
+pub mod lic;
+pub mod overlay;
+pub mod trails;
+
+pub use colormap::{Colormap, ColormapKind};
+pub use lic::Lic;
+pub use trails::TrailLayer;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::fluid_sim::FluidSim;
+use crate::view::ViewTransform;
+
+/// Paints a grid of values in [0, 1] over the whole screen buffer.
+///
+/// With `smooth` the values are bilinearly interpolated between cell centers
+/// instead of filling each cell with a flat block.
+pub fn upscale(values: &[f64], view: &ViewTransform, smooth: bool, colormap: &Colormap, buffer: &mut [u32]) {
+    for y in 0..view.screen_height {
+        let row = y * view.screen_width;
+        for x in 0..view.screen_width {
+            let (gx, gy) = view.screen_to_grid(x as f64 + 0.5, y as f64 + 0.5);
+            let v = if smooth {
+                FluidSim::sample_field(values, view.grid_width, view.grid_height, gx, gy)
+            } else {
+                let cx = (gx.round().max(0.0) as usize).min(view.grid_width - 1);
+                let cy = (gy.round().max(0.0) as usize).min(view.grid_height - 1);
+                values[cx + cy * view.grid_width]
+            };
+            buffer[row + x] = colormap.map(v);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+pub enum RenderField {
+    Density,
+    Speed,
+    Vorticity,
+    Pressure,
+    Divergence,
+}
+
+impl RenderField {
+    pub const ALL: [RenderField; 5] = [
+        RenderField::Density,
+        RenderField::Speed,
+        RenderField::Vorticity,
+        RenderField::Pressure,
+        RenderField::Divergence,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderField::Density => "Density",
+            RenderField::Speed => "Speed",
+            RenderField::Vorticity => "Vorticity",
+            RenderField::Pressure => "Pressure",
+            RenderField::Divergence => "Divergence",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|f| f == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// Fills `out` with the field normalized to [0, 1], one value per grid cell.
+    ///
+    /// Density is clamped as-is, speed is scaled by the current maximum and the
+    /// signed fields are centered on 0.5 so they read well on diverging colormaps.
+    pub fn sample(&self, fluid: &FluidSim, out: &mut Vec<f64>) {
+        out.clear();
+        out.resize(fluid.width * fluid.height, 0.0);
+
+        for y in 0..fluid.height {
+            for x in 0..fluid.width {
+                out[x + y * fluid.width] = match self {
+                    RenderField::Density => fluid.get_density(x, y),
+                    RenderField::Speed => fluid.speed(x, y),
+                    RenderField::Vorticity => fluid.vorticity(x, y),
+                    RenderField::Pressure => fluid.pressure[x + y * fluid.width],
+                    RenderField::Divergence => fluid.velocity_divergence(x, y),
+                };
+            }
+        }
+
+        match self {
+            RenderField::Density => {
+                for v in out.iter_mut() {
+                    *v = v.clamp(0.0, 1.0);
+                }
+            }
+            RenderField::Speed => {
+                let max = out.iter().cloned().fold(0.0, f64::max);
+                if max > 0.0 {
+                    for v in out.iter_mut() {
+                        *v /= max;
+                    }
+                }
+            }
+            RenderField::Vorticity | RenderField::Pressure | RenderField::Divergence => {
+                let max = out.iter().fold(0.0, |m: f64, v| m.max(v.abs()));
+                for v in out.iter_mut() {
+                    *v = if max > 0.0 { 0.5 + 0.5 * *v / max } else { 0.5 };
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::simulation::fluid_sim::FluidSim;
+use crate::view::ViewTransform;
+
+/// Screen-space distance between two arrows of the velocity overlay.
+const ARROW_SPACING: usize = 20;
+
+/// Draws a line into the buffer with Bresenham's algorithm, clipping off-screen pixels.
+pub fn draw_line(buffer: &mut [u32], width: usize, height: usize, from: (f64, f64), to: (f64, f64), color: u32) {
+    let (mut x0, mut y0) = (from.0.round() as isize, from.1.round() as isize);
+    let (x1, y1) = (to.0.round() as isize, to.1.round() as isize);
+
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+
+    loop {
+        if x0 >= 0 && y0 >= 0 && (x0 as usize) < width && (y0 as usize) < height {
+            buffer[x0 as usize + y0 as usize * width] = color;
+        }
+        if x0 == x1 && y0 == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x0 += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y0 += sy;
+        }
+    }
+}
+
+/// Draws a small square centered on a screen position.
+pub fn draw_point(buffer: &mut [u32], width: usize, height: usize, x: f64, y: f64, color: u32) {
+    let (cx, cy) = (x.round() as isize, y.round() as isize);
+    for py in cy - 1..=cy {
+        for px in cx - 1..=cx {
+            if px >= 0 && py >= 0 && (px as usize) < width && (py as usize) < height {
+                buffer[px as usize + py as usize * width] = color;
+            }
+        }
+    }
+}
+
+/// Draws an arrow per coarse sub-grid cell showing the direction of the flow.
+///
+/// Arrow lengths are relative to the fastest cell, so the overlay stays readable
+/// whatever the magnitude of the velocity field.
+pub fn draw_velocity_arrows(buffer: &mut [u32], fluid: &FluidSim, view: &ViewTransform, color: u32) {
+    let (ppc_x, ppc_y) = view.pixels_per_cell();
+    let (width, height) = (view.screen_width, view.screen_height);
+    let step = ((ARROW_SPACING as f64 / ppc_x.min(ppc_y)).round() as usize).max(1);
+    let max_len = (step as f64 * ppc_x.min(ppc_y)) * 0.9;
+
+    // Velocities are in cells per second, scale them to screen pixels
+    let screen_velocity = |idx: usize| (fluid.velocity_x[idx] * ppc_x, fluid.velocity_y[idx] * ppc_y);
+
+    let mut max_speed = 0.0f64;
+    for y in (step / 2..fluid.height).step_by(step) {
+        for x in (step / 2..fluid.width).step_by(step) {
+            let (vx, vy) = screen_velocity(x + y * fluid.width);
+            max_speed = max_speed.max(vx.hypot(vy));
+        }
+    }
+    if max_speed <= 1e-9 {
+        return;
+    }
+
+    for y in (step / 2..fluid.height).step_by(step) {
+        for x in (step / 2..fluid.width).step_by(step) {
+            let (vx, vy) = screen_velocity(x + y * fluid.width);
+            let (vx, vy) = (vx / max_speed, vy / max_speed);
+
+            let (cx, cy) = view.grid_to_screen(x as f64, y as f64);
+            let tip = (cx + vx * max_len, cy + vy * max_len);
+
+            draw_line(buffer, width, height, (cx, cy), tip, color);
+
+            // Arrow head: two short strokes rotated +-150 degrees from the shaft
+            let len = vx.hypot(vy) * max_len;
+            if len >= 4.0 {
+                let head = (len * 0.3).min(6.0);
+                let angle = vy.atan2(vx);
+                for side in [-1.0, 1.0] {
+                    let a = angle + side * 2.6;
+                    draw_line(buffer, width, height, tip, (tip.0 + a.cos() * head, tip.1 + a.sin() * head), color);
+                }
+            }
+        }
+    }
+}
+
+/// Fills the screen pixels of solid cells with a flat color.
+pub fn draw_obstacles(buffer: &mut [u32], fluid: &FluidSim, view: &ViewTransform, color: u32) {
+    if !fluid.obstacles.contains(&true) {
+        return;
+    }
+
+    for y in 0..view.screen_height {
+        for x in 0..view.screen_width {
+            let (gx, gy) = view.screen_to_grid(x as f64 + 0.5, y as f64 + 0.5);
+            let cx = (gx.round().max(0.0) as usize).min(fluid.width - 1);
+            let cy = (gy.round().max(0.0) as usize).min(fluid.height - 1);
+            if fluid.obstacles[cx + cy * fluid.width] {
+                buffer[x + y * view.screen_width] = color;
+            }
+        }
+    }
+}
+
+/// Outlines an emitter or sink at a grid position, with a stroke along its flow direction if any.
+pub fn draw_source(buffer: &mut [u32], view: &ViewTransform, x: f64, y: f64, radius: f64, velocity: Option<(f64, f64)>,
+    color: u32) {
+
+    let (width, height) = (view.screen_width, view.screen_height);
+    let (ppc_x, ppc_y) = view.pixels_per_cell();
+    let (cx, cy) = view.grid_to_screen(x, y);
+    let (rx, ry) = (radius.max(0.5) * ppc_x, radius.max(0.5) * ppc_y);
+
+    const SEGMENTS: usize = 24;
+    let point = |i: usize| {
+        let a = i as f64 / SEGMENTS as f64 * std::f64::consts::TAU;
+        (cx + rx * a.cos(), cy + ry * a.sin())
+    };
+    for i in 0..SEGMENTS {
+        draw_line(buffer, width, height, point(i), point(i + 1), color);
+    }
+
+    if let Some((vx, vy)) = velocity {
+        let speed = vx.hypot(vy);
+        if speed > 0.0 {
+            let tip = (cx + vx / speed * rx * 2.0, cy + vy / speed * ry * 2.0);
+            draw_line(buffer, width, height, (cx, cy), tip, color);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+
+use crate::view::ViewTransform;
+
+/// Intensity below which a trail point no longer shows.
+const VISIBLE: f32 = 0.01;
+
+/// Fading trails left by tracer particles.
+///
+/// The points are kept in grid cells, like the tracers, and mapped through the view
+/// when drawn so the trails stay in place as the view zooms and pans.
+pub struct TrailLayer {
+    /// Positions left by the tracers, oldest first, with the frame they were left at.
+    points: VecDeque<(f64, f64, u64)>,
+    frame: u64,
+    intensity: Vec<f32>,
+    pub fade: f32,
+}
+
+impl TrailLayer {
+    pub fn new(fade: f32) -> Self {
+        TrailLayer {
+            points: VecDeque::new(),
+            frame: 0,
+            intensity: Vec::new(),
+            fade,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    fn intensity_at(&self, frame: u64) -> f32 {
+        self.fade.powi((self.frame - frame).min(i32::MAX as u64) as i32)
+    }
+
+    /// Dims the existing trails and forgets those faded out, call once per frame before stamping.
+    pub fn decay(&mut self) {
+        self.frame += 1;
+        while let Some(&(_, _, frame)) = self.points.front() {
+            if self.intensity_at(frame) > VISIBLE {
+                break;
+            }
+            self.points.pop_front();
+        }
+    }
+
+    /// Marks a grid position at full intensity.
+    pub fn stamp(&mut self, x: f64, y: f64) {
+        self.points.push_back((x, y, self.frame));
+    }
+
+    /// Blends the trails seen through `view` over the buffer towards `color`.
+    pub fn composite(&mut self, buffer: &mut [u32], view: &ViewTransform, color: u32) {
+        let (width, height) = (view.screen_width, view.screen_height);
+        self.intensity.clear();
+        self.intensity.resize(width * height, 0.0);
+
+        // Points come grouped by frame, so the intensity only changes between groups
+        let mut current = None;
+        let mut t = 0.0;
+        for &(x, y, frame) in &self.points {
+            if current != Some(frame) {
+                current = Some(frame);
+                t = self.intensity_at(frame);
+            }
+            let (sx, sy) = view.grid_to_screen(x, y);
+            if sx < 0.0 || sy < 0.0 {
+                continue;
+            }
+            let (px, py) = (sx as usize, sy as usize);
+            if px < width && py < height {
+                let slot = &mut self.intensity[px + py * width];
+                *slot = slot.max(t);
+            }
+        }
+
+        for (pixel, &t) in buffer.iter_mut().zip(self.intensity.iter()) {
+            if t > VISIBLE {
+                *pixel = blend(*pixel, color, t);
+            }
+        }
+    }
+}
+
+fn blend(a: u32, b: u32, t: f32) -> u32 {
+    let channel = |shift: u32| {
+        let ca = ((a >> shift) & 0xFF) as f32;
+        let cb = ((b >> shift) & 0xFF) as f32;
+        ((ca + t * (cb - ca)) as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
@@ -0,0 +1,254 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::{Key, Stroke};
+use crate::simulation::fluid_sim::FluidSim;
+use crate::simulation::snapshot::Snapshot;
+
+/// Time step of input recordings, both while recording and when replaying.
+pub const RECORD_DT: f64 = 1.0 / 60.0;
+
+/// Keys that only read or write files, left out of recordings so a replay has no side effects.
+const FILE_KEYS: [Key; 7] = [Key::F5, Key::F9, Key::R, Key::E, Key::X, Key::D, Key::P];
+
+/// Whether a key only reads or writes files, those stay live during a replay.
+pub fn is_file_key(key: Key) -> bool {
+    FILE_KEYS.contains(&key)
+}
+
+/// First line of an input recording.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    dt: f64,
+    /// Snapshot of the simulation when the recording started, relative to the recording.
+    snapshot: PathBuf,
+    /// Scenario driving the simulation, relative to the recording, and its time when the
+    /// recording started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scenario: Option<PathBuf>,
+    #[serde(default)]
+    time: f64,
+    /// Whether the simulation was paused, and the steps it still had to take if so.
+    #[serde(default)]
+    paused: bool,
+    #[serde(default)]
+    step_frame: usize,
+    /// Live parameters when the recording started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    params: Option<LiveParams>,
+}
+
+/// Parameters of the settings panel that change how input affects the simulation,
+/// recorded whenever they change.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LiveParams {
+    pub diffusion_rate: f64,
+    pub pressure_iters: usize,
+    pub diffusion_iters: usize,
+    #[serde(default)]
+    pub conserve_mass: bool,
+    pub emitter_density: f64,
+    pub emitter_speed: f64,
+    pub emitter_angle: f64,
+    pub sink_rate: f64,
+}
+
+/// Where the simulation stood in time when a recording started.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Clock {
+    pub time: f64,
+    pub paused: bool,
+    /// Steps left to take while paused.
+    pub step_frame: usize,
+}
+
+/// Everything that affects the simulation during one frame.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct InputFrame {
+    pub frame: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<Key>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stroke: Option<Stroke>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<LiveParams>,
+}
+
+impl InputFrame {
+    fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.stroke.is_none() && self.params.is_none()
+    }
+}
+
+/// Writes the input of every frame to a JSON lines file, next to a snapshot of the
+/// state it started from.
+///
+/// Frames without any input are skipped, a final empty frame marks the end of the recording.
+pub struct InputRecorder {
+    pub path: PathBuf,
+    pub frame: u64,
+    params: LiveParams,
+    out: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn start(path: PathBuf, fluid: &FluidSim, params: LiveParams, scenario: Option<PathBuf>, clock: Clock)
+        -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let snapshot = path.with_extension("fsim");
+        Snapshot::save(&snapshot, fluid, params.pressure_iters, params.diffusion_iters)?;
+
+        let mut out = BufWriter::new(File::create(&path)?);
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let header = Header {
+            version: 1,
+            dt: RECORD_DT,
+            snapshot: snapshot.file_name().map(PathBuf::from).unwrap_or_default(),
+            // Relative so the recording can be moved or shared along with the scenario
+            scenario: scenario.map(|scenario| relative_to(&scenario, dir)),
+            time: clock.time,
+            paused: clock.paused,
+            step_frame: clock.step_frame,
+            params: Some(params),
+        };
+        writeln!(out, "{}", serde_json::to_string(&header)?)?;
+
+        Ok(InputRecorder { path, frame: 0, params, out })
+    }
+
+    /// Records the input of the next frame.
+    pub fn record(&mut self, keys: &[Key], stroke: Option<&Stroke>, params: LiveParams) -> io::Result<()> {
+        let keys: Vec<Key> = keys.iter().copied().filter(|&key| !is_file_key(key)).collect();
+        // The cursor only matters while painting or for keys seeding tracers at it
+        let stroke = stroke.filter(|s| s.density || s.impulse.is_some() || !keys.is_empty()).cloned();
+        let frame = InputFrame {
+            frame: self.frame,
+            keys,
+            stroke,
+            params: (params != self.params).then_some(params),
+        };
+        self.frame += 1;
+        self.params = params;
+
+        if frame.is_empty() {
+            return Ok(());
+        }
+        writeln!(self.out, "{}", serde_json::to_string(&frame)?)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        writeln!(self.out, "{}", serde_json::to_string(&InputFrame { frame: self.frame, ..Default::default() })?)?;
+        self.out.flush()
+    }
+}
+
+/// A loaded input recording, handing out its frames in order.
+pub struct InputReplay {
+    pub path: PathBuf,
+    pub dt: f64,
+    /// Snapshot of the simulation state the recording started from.
+    pub snapshot: PathBuf,
+    /// Scenario the recording ran, and its clock at the start of the recording.
+    pub scenario: Option<PathBuf>,
+    pub start_clock: Clock,
+    pub start_params: Option<LiveParams>,
+    /// Number of frames of the recording.
+    pub length: u64,
+    frames: Vec<InputFrame>,
+    next: usize,
+    pub frame: u64,
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut lines = BufReader::new(File::open(path)?).lines();
+
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).map_err(invalid)?,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "empty input recording")),
+        };
+        if header.version != 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("unsupported input recording version {}", header.version)));
+        }
+
+        let mut frames = Vec::new();
+        let mut length = 0;
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let frame: InputFrame = serde_json::from_str(&line).map_err(invalid)?;
+            if frame.is_empty() {
+                length = length.max(frame.frame);
+            } else {
+                length = length.max(frame.frame + 1);
+                frames.push(frame);
+            }
+        }
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Ok(InputReplay {
+            path: path.to_path_buf(),
+            dt: header.dt,
+            snapshot: dir.join(&header.snapshot),
+            // Recordings made before scenarios were stored relative have an absolute path, kept by `join`
+            scenario: header.scenario.map(|scenario| dir.join(scenario)),
+            start_clock: Clock { time: header.time, paused: header.paused, step_frame: header.step_frame },
+            start_params: header.params,
+            length,
+            frames,
+            next: 0,
+            frame: 0,
+        })
+    }
+
+    /// Loads the simulation state the recording started from.
+    pub fn load_start(&self) -> io::Result<Snapshot> {
+        Snapshot::load(&self.snapshot)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.length
+    }
+
+    /// The input of the next frame, empty for frames where nothing happened.
+    pub fn next_frame(&mut self) -> InputFrame {
+        let frame = self.frame;
+        self.frame += 1;
+
+        match self.frames.get(self.next) {
+            Some(recorded) if recorded.frame == frame => {
+                self.next += 1;
+                recorded.clone()
+            }
+            _ => InputFrame { frame, ..Default::default() },
+        }
+    }
+}
+
+/// `path` relative to the directory `base`, falling back to `path` when either doesn't exist.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let (Ok(path_abs), Ok(base_abs)) = (std::fs::canonicalize(path), std::fs::canonicalize(base)) else {
+        return path.to_path_buf();
+    };
+    let common = path_abs.components().zip(base_abs.components()).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return path_abs;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in base_abs.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(path_abs.components().skip(common));
+    relative
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use eframe::egui::Color32;
+use serde::{Deserialize, Serialize};
+
+use crate::export::{FieldFormat, LogFormat, RecordFormat};
+use crate::render::{ColormapKind, RenderField};
+use crate::simulation::brush::BrushShape;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationSettings {
+    pub width: usize,
+    pub height: usize,
+    pub particle_radius: usize,
+    pub brush_shape: BrushShape,
+    pub brush_strength: f64,
+    pub brush_force: f64,
+    pub eraser: bool,
+    pub precision: usize,
+    pub start_density: f64,
+    pub diffusion_rate: f64,
+    pub randomize: bool,
+    pub random_smoothing: usize,
+    pub pressure_iters: usize,
+    pub diffusion_iters: usize,
+    pub conserve_mass: bool,
+    pub colormap: ColormapKind,
+    pub gradient: Vec<Color32>,
+    pub field: RenderField,
+    pub lic: bool,
+    pub lic_modulate: bool,
+    pub smooth: bool,
+    pub record_stride: usize,
+    pub record_grid: bool,
+    pub record_format: RecordFormat,
+    pub record_scale: f64,
+    pub field_format: FieldFormat,
+    pub field_stride: usize,
+    pub log_format: LogFormat,
+    pub emitter_density: f64,
+    pub emitter_speed: f64,
+    pub emitter_angle: f64,
+    pub sink_rate: f64,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            particle_radius: 10,
+            brush_shape: BrushShape::Gaussian,
+            brush_strength: 3.0,
+            brush_force: 0.08,
+            eraser: false,
+            precision: 10,
+            start_density: 0.2,
+            diffusion_rate: 0.1,
+            randomize: false,
+            random_smoothing: 100,
+            pressure_iters: 3,
+            diffusion_iters: 3,
+            conserve_mass: false,
+            colormap: ColormapKind::Custom,
+            gradient: vec![Color32::BLACK, Color32::WHITE],
+            field: RenderField::Density,
+            lic: false,
+            lic_modulate: true,
+            smooth: false,
+            record_stride: 1,
+            record_grid: false,
+            record_format: RecordFormat::Png,
+            record_scale: 1.0,
+            field_format: FieldFormat::Vtk,
+            field_stride: 1,
+            log_format: LogFormat::Csv,
+            emitter_density: 1.0,
+            emitter_speed: 0.0,
+            emitter_angle: 0.0,
+            sink_rate: 1.0,
+        }
+    }
+}
+
+impl SimulationSettings {
+    /// Custom gradient stops packed as `0x00RRGGBB`, the pixel format of the render buffer.
+    pub fn gradient_stops(&self) -> Vec<u32> {
+        self.gradient
+            .iter()
+            .map(|c| ((c.r() as u32) << 16) | ((c.g() as u32) << 8) | (c.b() as u32))
+            .collect()
+    }
+}
+
+/// Settings persisted between launches: the last used settings and the named presets.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SettingsFile {
+    pub current: SimulationSettings,
+    pub presets: BTreeMap<String, SimulationSettings>,
+    /// Set when the file on disk couldn't be read nor backed up, it is then never overwritten.
+    #[serde(skip)]
+    pub read_only: bool,
+}
+
+impl SettingsFile {
+    pub const PATH: &'static str = "fluid_sim_settings.toml";
+
+    /// Loads the settings file, falling back to the defaults when it doesn't exist yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Loads the settings file, falling back to the defaults when it can't be read.
+    ///
+    /// An unreadable file is first copied next to itself with a `.bak` extension so that
+    /// saving the defaults doesn't lose its presets; the error says where it went.
+    pub fn load_or_backup(path: &Path) -> (Self, Option<String>) {
+        let error = match Self::load(path) {
+            Ok(file) => return (file, None),
+            Err(e) => e,
+        };
+
+        let backup = path.with_extension("toml.bak");
+        match std::fs::copy(path, &backup) {
+            Ok(_) => {
+                let message = format!("Unable to read {}: {error}, it was backed up to {}", path.display(), backup.display());
+                (Self::default(), Some(message))
+            }
+            Err(e) => {
+                let message = format!("Unable to read {}: {error}, settings won't be saved (backup failed: {e})",
+                    path.display());
+                (Self { read_only: true, ..Self::default() }, Some(message))
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::other("the file couldn't be read, saving would overwrite it"));
+        }
+        let text = toml::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, text)
+    }
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+/// What happens to the fluid at one edge of the domain.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Boundary {
+    /// A solid wall: the fluid stops and the dye touching it is removed.
+    #[default]
+    Wall,
+    /// An outflow: velocity and dye leave the domain as they reach the edge.
+    Open,
+}
+
+/// The boundary of each edge of the domain, `top` being the first row of the grid.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Boundaries {
+    pub left: Boundary,
+    pub right: Boundary,
+    pub top: Boundary,
+    pub bottom: Boundary,
+}
+
+impl Boundaries {
+    pub fn to_byte(self) -> u8 {
+        [self.left, self.right, self.top, self.bottom]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &b)| bits | ((b == Boundary::Open) as u8) << i)
+    }
+
+    pub fn from_byte(bits: u8) -> Self {
+        let side = |i: u8| if bits & (1 << i) != 0 { Boundary::Open } else { Boundary::Wall };
+        Boundaries {
+            left: side(0),
+            right: side(1),
+            top: side(2),
+            bottom: side(3),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use super::fluid_sim::FluidSim;
+
+/// Global measures of the flow, summed or maxed over the fluid cells with the
+/// border and obstacles left out. Velocities are in cells per second.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct FlowStats {
+    /// Total density.
+    pub mass: f64,
+    pub max_speed: f64,
+    /// Half the sum of the squared speeds.
+    pub kinetic_energy: f64,
+    /// Half the sum of the squared vorticities.
+    pub enstrophy: f64,
+}
+
+impl FlowStats {
+    pub fn measure(fluid: &FluidSim) -> Self {
+        let mut stats = FlowStats::default();
+        for y in 1..fluid.height - 1 {
+            for x in 1..fluid.width - 1 {
+                let idx = x + y * fluid.width;
+                if fluid.obstacles[idx] {
+                    continue;
+                }
+
+                let speed = fluid.speed(x, y);
+                stats.mass += fluid.density[idx];
+                stats.max_speed = stats.max_speed.max(speed);
+                stats.kinetic_energy += 0.5 * speed * speed;
+                stats.enstrophy += 0.5 * fluid.vorticity(x, y).powi(2);
+            }
+        }
+        stats
+    }
+}
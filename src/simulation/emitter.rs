@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+use super::fluid_sim::FluidSim;
+
+/// A round source of dye and/or flow at a fixed place of the grid.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Emitter {
+    /// Center in grid cells.
+    pub x: f64,
+    pub y: f64,
+    /// Radius in grid cells.
+    pub radius: f64,
+    /// Density added per second, each cell being capped at 1.
+    #[serde(default)]
+    pub density: f64,
+    /// Velocity imposed inside the emitter, in cells per second.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity: Option<(f64, f64)>,
+}
+
+impl Emitter {
+    /// The same emitter on a grid scaled by `(sx, sy)`, keeping it at the same place
+    /// relative to the cell centers.
+    pub fn scaled(&self, sx: f64, sy: f64) -> Self {
+        Emitter {
+            x: (self.x + 0.5) * sx - 0.5,
+            y: (self.y + 0.5) * sy - 0.5,
+            radius: self.radius * sx.min(sy),
+            density: self.density,
+            velocity: self.velocity.map(|(vx, vy)| (vx * sx, vy * sy)),
+        }
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        (x - self.x).powi(2) + (y - self.y).powi(2) <= self.radius.max(0.5).powi(2)
+    }
+
+    /// Injects `dt` seconds worth of the emitter into the fluid, leaving the border and obstacles alone.
+    pub fn apply(&self, fluid: &mut FluidSim, dt: f64) {
+        let r = self.radius.max(0.5);
+        let x0 = ((self.x - r).floor().max(1.0)) as usize;
+        let y0 = ((self.y - r).floor().max(1.0)) as usize;
+        let x1 = ((self.x + r).ceil().max(0.0) as usize).min(fluid.width.saturating_sub(2));
+        let y1 = ((self.y + r).ceil().max(0.0) as usize).min(fluid.height.saturating_sub(2));
+
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let idx = x + y * fluid.width;
+                if !self.contains(x as f64, y as f64) || fluid.obstacles[idx] {
+                    continue;
+                }
+
+                fluid.density[idx] = (fluid.density[idx] + self.density * dt).min(1.0);
+                if let Some((vx, vy)) = self.velocity {
+                    fluid.velocity_x[idx] = vx;
+                    fluid.velocity_y[idx] = vy;
+                }
+            }
+        }
+    }
+}
+
+/// A round drain removing dye at a fixed place of the grid.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Sink {
+    /// Center in grid cells.
+    pub x: f64,
+    pub y: f64,
+    /// Radius in grid cells.
+    pub radius: f64,
+    /// Density removed per second.
+    pub rate: f64,
+}
+
+impl Sink {
+    pub fn scaled(&self, sx: f64, sy: f64) -> Self {
+        Sink {
+            x: (self.x + 0.5) * sx - 0.5,
+            y: (self.y + 0.5) * sy - 0.5,
+            radius: self.radius * sx.min(sy),
+            rate: self.rate,
+        }
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        (x - self.x).powi(2) + (y - self.y).powi(2) <= self.radius.max(0.5).powi(2)
+    }
+
+    /// Removes `dt` seconds worth of dye, without going below zero.
+    pub fn apply(&self, fluid: &mut FluidSim, dt: f64) {
+        let r = self.radius.max(0.5);
+        let x0 = ((self.x - r).floor().max(0.0)) as usize;
+        let y0 = ((self.y - r).floor().max(0.0)) as usize;
+        let x1 = ((self.x + r).ceil().max(0.0) as usize).min(fluid.width - 1);
+        let y1 = ((self.y + r).ceil().max(0.0) as usize).min(fluid.height - 1);
+
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                if self.contains(x as f64, y as f64) {
+                    let idx = x + y * fluid.width;
+                    fluid.density[idx] = (fluid.density[idx] - self.rate * dt).max(0.0);
+                }
+            }
+        }
+    }
+}
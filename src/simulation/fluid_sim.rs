@@ -1,5 +1,44 @@
+use std::time::Instant;
+
 use rand::Rng;
 
+use super::boundary::{Boundaries, Boundary};
+use super::emitter::{Emitter, Sink};
+
+/// Where the dye of the fluid went during a step, in density summed over the fluid cells.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct MassBalance {
+    /// Total density when the step started.
+    pub start: f64,
+    /// Added by emitters, or removed by sinks when negative.
+    pub sources: f64,
+    /// Carried out through open edges, or in when negative.
+    pub outflow: f64,
+    /// Gained by diffusion and advection on top of the sources and the outflow,
+    /// lost when negative; the error the correction undoes.
+    pub drift: f64,
+    /// Added back by the mass correction.
+    pub correction: f64,
+    /// Total density when the step ended.
+    pub end: f64,
+}
+
+/// Wall clock time spent in each stage of the last step, in seconds.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct StageTimings {
+    pub sources: f64,
+    pub advect_velocity: f64,
+    pub projection: f64,
+    pub diffusion: f64,
+    pub advect_density: f64,
+}
+
+impl StageTimings {
+    pub fn total(&self) -> f64 {
+        self.sources + self.advect_velocity + self.projection + self.diffusion + self.advect_density
+    }
+}
+
 pub struct FluidSim {
     pub width: usize,
     pub height: usize,
@@ -9,6 +48,27 @@ pub struct FluidSim {
     pub diffusion_rate: f64,
     pub pressure: Vec<f64>,
     pub divergence: Vec<f64>,
+    pub boundaries: Boundaries,
+    /// Solid cells, where the fluid doesn't flow and dye can't enter.
+    pub obstacles: Vec<bool>,
+    /// Sources and drains applied at the start of every step.
+    pub emitters: Vec<Emitter>,
+    pub sinks: Vec<Sink>,
+    /// Rescales the density after each step so that only sources, sinks and open
+    /// edges change the total mass.
+    pub conserve_mass: bool,
+    /// Measures the mass budget, divergence and pressure residual of every step, each
+    /// a few extra passes over the grid. The mass budget is also kept to conserve mass.
+    pub measure: bool,
+    /// Mass budget of the last step, all zero when it isn't measured.
+    pub mass: MassBalance,
+    pub timings: StageTimings,
+    /// Largest divergence of the fluid cells right after the last projection,
+    /// zero for a perfectly incompressible flow, `None` when it wasn't measured.
+    pub max_divergence: Option<f64>,
+    /// Largest residual of the pressure equation after the last solve, `None` when
+    /// it wasn't measured.
+    pub pressure_residual: Option<f64>,
 
     density_temp: Vec<f64>,
     velocity_x_temp: Vec<f64>,
@@ -32,12 +92,59 @@ impl FluidSim {
             diffusion_rate,
             pressure: vec![0.0; size],
             divergence: vec![0.0; size],
+            boundaries: Boundaries::default(),
+            obstacles: vec![false; size],
+            emitters: Vec::new(),
+            sinks: Vec::new(),
+            conserve_mass: false,
+            measure: false,
+            mass: MassBalance::default(),
+            timings: StageTimings::default(),
+            max_divergence: None,
+            pressure_residual: None,
             density_temp: vec![0.0; size],
             velocity_x_temp: vec![0.0; size],
             velocity_y_temp: vec![0.0; size],
         }
     }
 
+    /// Builds a copy of the simulation on a grid of a different size.
+    ///
+    /// Fields are bilinearly resampled; velocities are in cells per second so
+    /// they are rescaled along each axis to keep the same motion on screen.
+    pub fn resampled(&self, width: usize, height: usize) -> Self {
+        let mut resized = FluidSim::new(width, height, 0.0, self.diffusion_rate);
+        resized.boundaries = self.boundaries;
+        resized.conserve_mass = self.conserve_mass;
+        resized.measure = self.measure;
+
+        let sx = self.width as f64 / width as f64;
+        let sy = self.height as f64 / height as f64;
+
+        for y in 0..height {
+            for x in 0..width {
+                // Map cell centers so the edges of both grids line up
+                let px = (x as f64 + 0.5) * sx - 0.5;
+                let py = (y as f64 + 0.5) * sy - 0.5;
+                let idx = x + y * width;
+
+                resized.density[idx] = Self::sample_field(&self.density, self.width, self.height, px, py);
+                resized.velocity_x[idx] = Self::sample_field(&self.velocity_x, self.width, self.height, px, py) / sx;
+                resized.velocity_y[idx] = Self::sample_field(&self.velocity_y, self.width, self.height, px, py) / sy;
+                resized.pressure[idx] = Self::sample_field(&self.pressure, self.width, self.height, px, py);
+
+                let ox = (px.round().max(0.0) as usize).min(self.width - 1);
+                let oy = (py.round().max(0.0) as usize).min(self.height - 1);
+                resized.obstacles[idx] = self.obstacles[self.idx(ox, oy)];
+            }
+        }
+
+        resized.emitters = self.emitters.iter().map(|e| e.scaled(1.0 / sx, 1.0 / sy)).collect();
+        resized.sinks = self.sinks.iter().map(|s| s.scaled(1.0 / sx, 1.0 / sy)).collect();
+
+        resized
+    }
+
     pub fn randomize_density_smoothed(&mut self, seed_count: usize) {
         let mut rng = rand::thread_rng();
 
@@ -59,6 +166,109 @@ impl FluidSim {
         self.density[self.idx(x, y)]
     }
 
+    pub fn speed(&self, x: usize, y: usize) -> f64 {
+        let idx = self.idx(x, y);
+        self.velocity_x[idx].hypot(self.velocity_y[idx])
+    }
+
+    /// Curl of the velocity field (central differences), zero on the border.
+    pub fn vorticity(&self, x: usize, y: usize) -> f64 {
+        if x == 0 || y == 0 || x >= self.width - 1 || y >= self.height - 1 {
+            return 0.0;
+        }
+        0.5 * (
+            self.velocity_y[self.idx(x + 1, y)] - self.velocity_y[self.idx(x - 1, y)] -
+            self.velocity_x[self.idx(x, y + 1)] + self.velocity_x[self.idx(x, y - 1)]
+        )
+    }
+
+    /// Divergence of the current velocity field (central differences), zero on the border.
+    pub fn velocity_divergence(&self, x: usize, y: usize) -> f64 {
+        if x == 0 || y == 0 || x >= self.width - 1 || y >= self.height - 1 {
+            return 0.0;
+        }
+        0.5 * (
+            self.velocity_x[self.idx(x + 1, y)] - self.velocity_x[self.idx(x - 1, y)] +
+            self.velocity_y[self.idx(x, y + 1)] - self.velocity_y[self.idx(x, y - 1)]
+        )
+    }
+
+    /// Largest divergence of the fluid cells, in absolute value.
+    pub fn max_velocity_divergence(&self) -> f64 {
+        let mut max = 0.0f64;
+        for y in 1..self.height - 1 {
+            for x in 1..self.width - 1 {
+                if !self.obstacles[self.idx(x, y)] {
+                    max = max.max(self.velocity_divergence(x, y).abs());
+                }
+            }
+        }
+        max
+    }
+
+    /// Total density of the fluid cells, the border and obstacles left out.
+    pub fn total_mass(&self) -> f64 {
+        let mut mass = 0.0;
+        for y in 1..self.height - 1 {
+            for x in 1..self.width - 1 {
+                let idx = self.idx(x, y);
+                if !self.obstacles[idx] {
+                    mass += self.density[idx];
+                }
+            }
+        }
+        mass
+    }
+
+    /// Density carried through the open edges by `dt` seconds of advection, negative
+    /// when more comes in than goes out.
+    ///
+    /// Open edges copy the cell next to them, so the dye crossing an edge is the one
+    /// of that cell moving at its normal velocity.
+    fn outflow(&self, dt: f64) -> f64 {
+        let mut flux = 0.0;
+        let mut edge = |boundary: Boundary, idx: usize, normal_velocity: f64| {
+            if boundary == Boundary::Open && !self.obstacles[idx] {
+                // A cell can't give away more dye than it holds
+                flux += self.density[idx] * (normal_velocity * dt).clamp(-1.0, 1.0);
+            }
+        };
+
+        for y in 1..self.height - 1 {
+            let left = self.idx(1, y);
+            let right = self.idx(self.width - 2, y);
+            edge(self.boundaries.left, left, -self.velocity_x[left]);
+            edge(self.boundaries.right, right, self.velocity_x[right]);
+        }
+        for x in 1..self.width - 1 {
+            let top = self.idx(x, 1);
+            let bottom = self.idx(x, self.height - 2);
+            edge(self.boundaries.top, top, -self.velocity_y[top]);
+            edge(self.boundaries.bottom, bottom, self.velocity_y[bottom]);
+        }
+        flux
+    }
+
+    /// Scales the density of the fluid cells so they add up to `target`, returning the
+    /// mass added. Scaling keeps the shape of the dye and never makes it negative, so
+    /// when the budget leaves no dye at all the density is cleared.
+    fn correct_mass(&mut self, mass: f64, target: f64) -> f64 {
+        if mass <= 0.0 {
+            return 0.0;
+        }
+        let target = target.max(0.0);
+        let scale = target / mass;
+        for y in 1..self.height - 1 {
+            for x in 1..self.width - 1 {
+                let idx = self.idx(x, y);
+                if !self.obstacles[idx] {
+                    self.density[idx] *= scale;
+                }
+            }
+        }
+        target - mass
+    }
+
     pub fn diffuse_density(&mut self, dt: f64, diffusion_iters: usize) {
         let a = self.diffusion_rate * dt;
 
@@ -106,7 +316,8 @@ impl FluidSim {
         a + t * (b - a)
     }
 
-    fn sample_field(field: &Vec<f64>, width: usize, height: usize, x: f64, y: f64) -> f64 {
+    /// Bilinearly interpolates a cell-centered field at a fractional grid position.
+    pub fn sample_field(field: &[f64], width: usize, height: usize, x: f64, y: f64) -> f64 {
         let w = width as isize;
         let h = height as isize;
 
@@ -133,6 +344,14 @@ impl FluidSim {
         Self::lerp(a, b, sy)
     }
 
+    /// Bilinearly interpolated velocity at a fractional grid position.
+    pub fn sample_velocity(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            Self::sample_field(&self.velocity_x, self.width, self.height, x, y),
+            Self::sample_field(&self.velocity_y, self.width, self.height, x, y),
+        )
+    }
+
     pub fn advect_density(&mut self, dt: f64) {
         // Copy current density to temp buffer
         self.density_temp.copy_from_slice(&self.density);
@@ -152,20 +371,10 @@ impl FluidSim {
         }
 
         std::mem::swap(&mut self.density, &mut self.density_temp);
-        
-        // Clear boundaries
-        for y in 0..self.height {
-            let idx_left = self.idx(0, y);
-            let idx_right = self.idx(self.width - 1, y);
-            self.density[idx_left] = 0.0;
-            self.density[idx_right] = 0.0;
-        }
-        for x in 0..self.width {
-            let idx_top = self.idx(x, 0);
-            let idx_bottom = self.idx(x, self.height - 1);
-            self.density[idx_top] = 0.0;
-            self.density[idx_bottom] = 0.0;
-        }
+
+        // Walls absorb the dye, open edges let it flow out
+        Self::apply_boundaries(&mut self.density, self.width, self.height, self.boundaries);
+        self.clear_obstacles_density();
     }
 
     pub fn advect_velocity(&mut self, dt: f64) {
@@ -189,22 +398,44 @@ impl FluidSim {
 
         std::mem::swap(&mut self.velocity_x, &mut self.velocity_x_temp);
         std::mem::swap(&mut self.velocity_y, &mut self.velocity_y_temp);
-        
-        for y in 0..self.height {
-            let idx_left = self.idx(0, y);
-            let idx_right = self.idx(self.width - 1, y);
-            self.velocity_x[idx_left] = 0.0;
-            self.velocity_x[idx_right] = 0.0;
-            self.velocity_y[idx_left] = 0.0;
-            self.velocity_y[idx_right] = 0.0;
+
+        Self::apply_boundaries(&mut self.velocity_x, self.width, self.height, self.boundaries);
+        Self::apply_boundaries(&mut self.velocity_y, self.width, self.height, self.boundaries);
+        self.clear_obstacles_velocity();
+    }
+
+    /// Sets the border of a field: zero along walls, a copy of the neighbouring
+    /// interior cell along open edges.
+    fn apply_boundaries(field: &mut [f64], width: usize, height: usize, boundaries: Boundaries) {
+        let edge = |boundary: Boundary, inner: f64| match boundary {
+            Boundary::Wall => 0.0,
+            Boundary::Open => inner,
+        };
+
+        for y in 0..height {
+            field[y * width] = edge(boundaries.left, field[1 + y * width]);
+            field[width - 1 + y * width] = edge(boundaries.right, field[width - 2 + y * width]);
+        }
+        for x in 0..width {
+            field[x] = edge(boundaries.top, field[x + width]);
+            field[x + (height - 1) * width] = edge(boundaries.bottom, field[x + (height - 2) * width]);
         }
-        for x in 0..self.width {
-            let idx_top = self.idx(x, 0);
-            let idx_bottom = self.idx(x, self.height - 1);
-            self.velocity_x[idx_top] = 0.0;
-            self.velocity_x[idx_bottom] = 0.0;
-            self.velocity_y[idx_top] = 0.0;
-            self.velocity_y[idx_bottom] = 0.0;
+    }
+
+    fn clear_obstacles_velocity(&mut self) {
+        for (idx, &solid) in self.obstacles.iter().enumerate() {
+            if solid {
+                self.velocity_x[idx] = 0.0;
+                self.velocity_y[idx] = 0.0;
+            }
+        }
+    }
+
+    fn clear_obstacles_density(&mut self) {
+        for (idx, &solid) in self.obstacles.iter().enumerate() {
+            if solid {
+                self.density[idx] = 0.0;
+            }
         }
     }
 
@@ -217,6 +448,11 @@ impl FluidSim {
                 let idx_up = self.idx(x, y + 1);
                 let idx_down = self.idx(x, y - 1);
                 
+                if self.obstacles[idx] {
+                    self.divergence[idx] = 0.0;
+                    continue;
+                }
+
                 let divergence = 0.5 * (
                     self.velocity_x[idx_right] - self.velocity_x[idx_left] +
                     self.velocity_y[idx_up] - self.velocity_y[idx_down]
@@ -238,10 +474,15 @@ impl FluidSim {
                     let idx_up = self.idx(x, y + 1);
                     let idx_down = self.idx(x, y - 1);
                     
-                    let neighbors = self.pressure[idx_right] +
-                                   self.pressure[idx_left] +
-                                   self.pressure[idx_up] +
-                                   self.pressure[idx_down];
+                    if self.obstacles[idx] {
+                        continue;
+                    }
+
+                    // Solid neighbours mirror the cell so no flow is pushed through them
+                    let neighbors = self.pressure_or_own(idx_right, idx) +
+                                   self.pressure_or_own(idx_left, idx) +
+                                   self.pressure_or_own(idx_up, idx) +
+                                   self.pressure_or_own(idx_down, idx);
                     self.pressure[idx] = (neighbors + self.divergence[idx]) / 4.0;
                 }
             }
@@ -262,6 +503,30 @@ impl FluidSim {
         }
     }
 
+    /// Largest error left in the pressure equation by the iterations of the last solve.
+    fn max_pressure_residual(&self) -> f64 {
+        let mut max = 0.0f64;
+        for y in 1..self.height - 1 {
+            for x in 1..self.width - 1 {
+                let idx = self.idx(x, y);
+                if self.obstacles[idx] {
+                    continue;
+                }
+                let neighbors = self.pressure_or_own(self.idx(x + 1, y), idx) +
+                               self.pressure_or_own(self.idx(x - 1, y), idx) +
+                               self.pressure_or_own(self.idx(x, y + 1), idx) +
+                               self.pressure_or_own(self.idx(x, y - 1), idx);
+                max = max.max((neighbors + self.divergence[idx] - 4.0 * self.pressure[idx]).abs());
+            }
+        }
+        max
+    }
+
+    #[inline]
+    fn pressure_or_own(&self, neighbor: usize, idx: usize) -> f64 {
+        if self.obstacles[neighbor] { self.pressure[idx] } else { self.pressure[neighbor] }
+    }
+
     fn correct_velocity(&mut self) {
         for x in 1..self.width - 1 {
             for y in 1..self.height - 1 {
@@ -271,8 +536,12 @@ impl FluidSim {
                 let idx_up = self.idx(x, y + 1);
                 let idx_down = self.idx(x, y - 1);
                 
-                let pressure_gradient_x = 0.5 * (self.pressure[idx_right] - self.pressure[idx_left]);
-                let pressure_gradient_y = 0.5 * (self.pressure[idx_up] - self.pressure[idx_down]);
+                if self.obstacles[idx] {
+                    continue;
+                }
+
+                let pressure_gradient_x = 0.5 * (self.pressure_or_own(idx_right, idx) - self.pressure_or_own(idx_left, idx));
+                let pressure_gradient_y = 0.5 * (self.pressure_or_own(idx_up, idx) - self.pressure_or_own(idx_down, idx));
                 
                 self.velocity_x[idx] -= pressure_gradient_x;
                 self.velocity_y[idx] -= pressure_gradient_y;
@@ -287,11 +556,63 @@ impl FluidSim {
     }
 
 
+    pub fn apply_sources(&mut self, dt: f64) {
+        let emitters = std::mem::take(&mut self.emitters);
+        for emitter in &emitters {
+            emitter.apply(self, dt);
+        }
+        self.emitters = emitters;
+
+        let sinks = std::mem::take(&mut self.sinks);
+        for sink in &sinks {
+            sink.apply(self, dt);
+        }
+        self.sinks = sinks;
+    }
+
+    /// Advances the simulation by `dt` seconds and fills in the mass budget, timings,
+    /// divergence and pressure residual of the step.
     pub fn step(&mut self, dt: f64, pressure_iterations: usize, diffusion_iterations: usize) {
+        let budget = self.conserve_mass || self.measure;
+        let start = if budget { self.total_mass() } else { 0.0 };
+        let clock = Instant::now();
+        self.apply_sources(dt);
+        self.timings.sources = clock.elapsed().as_secs_f64();
+        let sourced = if budget { self.total_mass() } else { 0.0 };
+
+        let clock = Instant::now();
         self.advect_velocity(dt);
+        self.timings.advect_velocity = clock.elapsed().as_secs_f64();
+
+        let clock = Instant::now();
         self.enforce_incompressibility(pressure_iterations);
+        self.timings.projection = clock.elapsed().as_secs_f64();
+        self.max_divergence = self.measure.then(|| self.max_velocity_divergence());
+        self.pressure_residual = self.measure.then(|| self.max_pressure_residual());
 
+        let clock = Instant::now();
         self.diffuse_density(dt, diffusion_iterations);
+        self.timings.diffusion = clock.elapsed().as_secs_f64();
+
+        let outflow = if budget { self.outflow(dt) } else { 0.0 };
+        let clock = Instant::now();
         self.advect_density(dt);
+        self.timings.advect_density = clock.elapsed().as_secs_f64();
+
+        if !budget {
+            self.mass = MassBalance::default();
+            return;
+        }
+        let transported = self.total_mass();
+        let expected = sourced - outflow;
+        let correction = if self.conserve_mass { self.correct_mass(transported, expected) } else { 0.0 };
+        self.mass = MassBalance {
+            start,
+            sources: sourced - start,
+            outflow,
+            drift: transported - expected,
+            correction,
+            end: transported + correction,
+        };
     }
 }
\ No newline at end of file
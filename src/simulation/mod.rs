@@ -1 +1,11 @@
+pub mod boundary;
+pub mod brush;
+pub mod diagnostics;
+pub mod emitter;
 pub mod fluid_sim;
+pub mod scenario;
+pub mod tracers;
+pub mod snapshot;
+
+#[cfg(test)]
+mod validation;
@@ -0,0 +1,150 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::boundary::Boundaries;
+use super::emitter::Emitter;
+use super::fluid_sim::FluidSim;
+
+/// A versionable experiment: the domain with its boundaries and obstacles, timed
+/// sources and forces, and the solver settings to run it with.
+///
+/// Positions and sizes are in cells of `grid`, they are scaled when the simulation
+/// runs on another grid.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    /// Grid size in cells.
+    pub grid: (usize, usize),
+    #[serde(default)]
+    pub start_density: f64,
+    /// Simulated seconds a headless run lasts when no step count is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub solver: ScenarioSolver,
+    #[serde(default)]
+    pub boundaries: Boundaries,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub obstacles: Vec<Obstacle>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emitters: Vec<TimedEmitter>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forces: Vec<Force>,
+    /// File the scenario was loaded from.
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// Solver settings of a scenario, the ones left out keep their current value.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct ScenarioSolver {
+    pub diffusion_rate: Option<f64>,
+    pub pressure_iters: Option<usize>,
+    pub diffusion_iters: Option<usize>,
+    pub conserve_mass: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "lowercase")]
+pub enum Obstacle {
+    Circle { x: f64, y: f64, radius: f64 },
+    /// An axis aligned rectangle from its top left corner.
+    Rect { x: f64, y: f64, width: f64, height: f64 },
+}
+
+impl Obstacle {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        match *self {
+            Obstacle::Circle { x: cx, y: cy, radius } => (x - cx).powi(2) + (y - cy).powi(2) <= radius * radius,
+            Obstacle::Rect { x: rx, y: ry, width, height } => x >= rx && x < rx + width && y >= ry && y < ry + height,
+        }
+    }
+}
+
+/// An emitter active from `start` until `end` seconds, or forever.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimedEmitter {
+    #[serde(flatten)]
+    pub emitter: Emitter,
+    #[serde(default)]
+    pub start: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+}
+
+/// A uniform acceleration of the whole fluid, such as gravity, active from `start`
+/// until `end` seconds, or forever.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Force {
+    /// Acceleration in cells per second squared.
+    pub acceleration: (f64, f64),
+    #[serde(default)]
+    pub start: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+}
+
+fn is_active(time: f64, start: f64, end: Option<f64>) -> bool {
+    time >= start && end.is_none_or(|end| time < end)
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut scenario: Scenario = toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if scenario.grid.0 < 3 || scenario.grid.1 < 3 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the scenario grid needs at least 3x3 cells"));
+        }
+        scenario.path = path.to_path_buf();
+        Ok(scenario)
+    }
+
+    /// A fresh simulation of the scenario's domain at its starting state.
+    pub fn build(&self, diffusion_rate: f64) -> FluidSim {
+        let (width, height) = self.grid;
+        let mut fluid = FluidSim::new(width, height, self.start_density, self.solver.diffusion_rate.unwrap_or(diffusion_rate));
+        fluid.boundaries = self.boundaries;
+
+        for y in 0..height {
+            for x in 0..width {
+                if self.obstacles.iter().any(|o| o.contains(x as f64, y as f64)) {
+                    let idx = x + y * width;
+                    fluid.obstacles[idx] = true;
+                    fluid.density[idx] = 0.0;
+                }
+            }
+        }
+        fluid
+    }
+
+    /// Applies the emitters and forces active at `time` for a step of `dt` seconds.
+    pub fn apply(&self, fluid: &mut FluidSim, time: f64, dt: f64) {
+        let sx = fluid.width as f64 / self.grid.0 as f64;
+        let sy = fluid.height as f64 / self.grid.1 as f64;
+
+        for timed in &self.emitters {
+            if is_active(time, timed.start, timed.end) {
+                timed.emitter.scaled(sx, sy).apply(fluid, dt);
+            }
+        }
+
+        for force in &self.forces {
+            if !is_active(time, force.start, force.end) {
+                continue;
+            }
+            let (ax, ay) = (force.acceleration.0 * sx * dt, force.acceleration.1 * sy * dt);
+            for y in 1..fluid.height - 1 {
+                for x in 1..fluid.width - 1 {
+                    let idx = x + y * fluid.width;
+                    if !fluid.obstacles[idx] {
+                        fluid.velocity_x[idx] += ax;
+                        fluid.velocity_y[idx] += ay;
+                    }
+                }
+            }
+        }
+    }
+}
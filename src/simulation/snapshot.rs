@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::boundary::Boundaries;
+use super::emitter::{Emitter, Sink};
+use super::fluid_sim::FluidSim;
+
+const MAGIC: &[u8; 4] = b"FSIM";
+const VERSION: u32 = 3;
+
+/// Full simulation state as stored on disk, along with the solver settings it ran with.
+///
+/// The format is little-endian: magic, version, grid size, parameters, then the
+/// `density`, `velocity_x`, `velocity_y` and `pressure` fields as `f64` arrays.
+/// Since version 2 they are followed by the boundaries and the obstacles, one byte each,
+/// and since version 3 by the emitters and sinks, each list preceded by its length.
+pub struct Snapshot {
+    pub fluid: FluidSim,
+    pub pressure_iters: usize,
+    pub diffusion_iters: usize,
+}
+
+impl Snapshot {
+    pub fn write(fluid: &FluidSim, pressure_iters: usize, diffusion_iters: usize, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&(fluid.width as u64).to_le_bytes())?;
+        w.write_all(&(fluid.height as u64).to_le_bytes())?;
+        w.write_all(&fluid.diffusion_rate.to_le_bytes())?;
+        w.write_all(&(pressure_iters as u64).to_le_bytes())?;
+        w.write_all(&(diffusion_iters as u64).to_le_bytes())?;
+
+        for field in [&fluid.density, &fluid.velocity_x, &fluid.velocity_y, &fluid.pressure] {
+            for v in field.iter() {
+                w.write_all(&v.to_le_bytes())?;
+            }
+        }
+
+        w.write_all(&[fluid.boundaries.to_byte()])?;
+        let obstacles: Vec<u8> = fluid.obstacles.iter().map(|&solid| solid as u8).collect();
+        w.write_all(&obstacles)?;
+
+        w.write_all(&(fluid.emitters.len() as u64).to_le_bytes())?;
+        for emitter in &fluid.emitters {
+            let (vx, vy) = emitter.velocity.unwrap_or((0.0, 0.0));
+            for v in [emitter.x, emitter.y, emitter.radius, emitter.density, vx, vy] {
+                w.write_all(&v.to_le_bytes())?;
+            }
+            w.write_all(&[emitter.velocity.is_some() as u8])?;
+        }
+        w.write_all(&(fluid.sinks.len() as u64).to_le_bytes())?;
+        for sink in &fluid.sinks {
+            for v in [sink.x, sink.y, sink.radius, sink.rate] {
+                w.write_all(&v.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a fluid simulation snapshot"));
+        }
+        let version = read_u32(r)?;
+        if version == 0 || version > VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported snapshot version {version}")));
+        }
+
+        let width = read_u64(r)? as usize;
+        let height = read_u64(r)? as usize;
+        let field_bytes = width.checked_mul(height).and_then(|cells| cells.checked_mul(8));
+        let Some(field_bytes) = field_bytes.filter(|_| width >= 3 && height >= 3) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid snapshot grid size {width}x{height}")));
+        };
+        let diffusion_rate = read_f64(r)?;
+        let pressure_iters = read_u64(r)? as usize;
+        let diffusion_iters = read_u64(r)? as usize;
+
+        // The fields are read before the grid is allocated, so a corrupt size fails at the
+        // end of the file instead of allocating whatever it claims
+        let density = read_field(r, field_bytes)?;
+        let velocity_x = read_field(r, field_bytes)?;
+        let velocity_y = read_field(r, field_bytes)?;
+        let pressure = read_field(r, field_bytes)?;
+        let mut fluid = FluidSim::new(width, height, 0.0, diffusion_rate);
+        fluid.density = density;
+        fluid.velocity_x = velocity_x;
+        fluid.velocity_y = velocity_y;
+        fluid.pressure = pressure;
+
+        if version >= 2 {
+            let mut boundaries = [0u8; 1];
+            r.read_exact(&mut boundaries)?;
+            fluid.boundaries = Boundaries::from_byte(boundaries[0]);
+
+            let mut obstacles = vec![0u8; width * height];
+            r.read_exact(&mut obstacles)?;
+            fluid.obstacles = obstacles.iter().map(|&b| b != 0).collect();
+        }
+
+        if version >= 3 {
+            for _ in 0..read_u64(r)? {
+                let (x, y, radius, density) = (read_f64(r)?, read_f64(r)?, read_f64(r)?, read_f64(r)?);
+                let (vx, vy) = (read_f64(r)?, read_f64(r)?);
+                let mut has_velocity = [0u8; 1];
+                r.read_exact(&mut has_velocity)?;
+                fluid.emitters.push(Emitter { x, y, radius, density, velocity: (has_velocity[0] != 0).then_some((vx, vy)) });
+            }
+            for _ in 0..read_u64(r)? {
+                let (x, y, radius, rate) = (read_f64(r)?, read_f64(r)?, read_f64(r)?, read_f64(r)?);
+                fluid.sinks.push(Sink { x, y, radius, rate });
+            }
+        }
+
+        Ok(Snapshot {
+            fluid,
+            pressure_iters,
+            diffusion_iters,
+        })
+    }
+
+    pub fn save(path: &Path, fluid: &FluidSim, pressure_iters: usize, diffusion_iters: usize) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut w = BufWriter::new(File::create(path)?);
+        Self::write(fluid, pressure_iters, diffusion_iters, &mut w)?;
+        w.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Reads a field of `bytes` bytes, growing the buffer only as the data comes in.
+fn read_field(r: &mut impl Read, bytes: usize) -> io::Result<Vec<f64>> {
+    let mut data = Vec::new();
+    r.take(bytes as u64).read_to_end(&mut data)?;
+    if data.len() < bytes {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "snapshot ends before its fields"));
+    }
+    Ok(data
+        .chunks_exact(8)
+        .map(|chunk| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(chunk);
+            f64::from_le_bytes(bytes)
+        })
+        .collect())
+}
+
+fn read_f64(r: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0u8; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::boundary::Boundary;
+
+    /// Size of the version 1 part of a snapshot: magic, version, sizes, parameters and fields.
+    fn fields_end(fluid: &FluidSim) -> usize {
+        4 + 4 + 5 * 8 + 4 * 8 * fluid.width * fluid.height
+    }
+
+    fn sample() -> FluidSim {
+        let mut fluid = FluidSim::new(7, 5, 0.0, 0.25);
+        let cells = fluid.width * fluid.height;
+        fluid.density = (0..cells).map(|i| i as f64 / 10.0).collect();
+        fluid.velocity_x = (0..cells).map(|i| -(i as f64)).collect();
+        fluid.velocity_y = (0..cells).map(|i| (i as f64).sqrt()).collect();
+        fluid.pressure = (0..cells).map(|i| 1.0 / (i as f64 + 1.0)).collect();
+        fluid.boundaries.right = Boundary::Open;
+        fluid.obstacles[8] = true;
+        fluid.emitters.push(Emitter { x: 2.0, y: 3.0, radius: 1.5, density: 0.5, velocity: Some((1.0, -2.0)) });
+        fluid.emitters.push(Emitter { x: 4.0, y: 1.0, radius: 1.0, density: 2.0, velocity: None });
+        fluid.sinks.push(Sink { x: 5.0, y: 2.0, radius: 0.5, rate: 3.0 });
+        fluid
+    }
+
+    fn write(fluid: &FluidSim) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        Snapshot::write(fluid, 12, 4, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn assert_same_fields(a: &FluidSim, b: &FluidSim) {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        assert_eq!(a.diffusion_rate, b.diffusion_rate);
+        assert_eq!(a.density, b.density);
+        assert_eq!(a.velocity_x, b.velocity_x);
+        assert_eq!(a.velocity_y, b.velocity_y);
+        assert_eq!(a.pressure, b.pressure);
+    }
+
+    #[test]
+    fn round_trip_keeps_the_whole_state() {
+        let fluid = sample();
+        let snapshot = Snapshot::read(&mut write(&fluid).as_slice()).unwrap();
+
+        assert_same_fields(&snapshot.fluid, &fluid);
+        assert_eq!((snapshot.pressure_iters, snapshot.diffusion_iters), (12, 4));
+        assert_eq!(snapshot.fluid.boundaries, fluid.boundaries);
+        assert_eq!(snapshot.fluid.obstacles, fluid.obstacles);
+        assert_eq!(snapshot.fluid.emitters, fluid.emitters);
+        assert_eq!(snapshot.fluid.sinks, fluid.sinks);
+    }
+
+    #[test]
+    fn older_versions_load_with_defaults_for_what_they_lack() {
+        let fluid = sample();
+        let bytes = write(&fluid);
+        let with_version = |version: u32, length: usize| {
+            let mut old = bytes[..length].to_vec();
+            old[4..8].copy_from_slice(&version.to_le_bytes());
+            old
+        };
+
+        let v1 = Snapshot::read(&mut with_version(1, fields_end(&fluid)).as_slice()).unwrap();
+        assert_same_fields(&v1.fluid, &fluid);
+        assert_eq!(v1.fluid.boundaries, Boundaries::default());
+        assert!(v1.fluid.obstacles.iter().all(|&solid| !solid));
+        assert!(v1.fluid.emitters.is_empty() && v1.fluid.sinks.is_empty());
+
+        let v2_end = fields_end(&fluid) + 1 + fluid.width * fluid.height;
+        let v2 = Snapshot::read(&mut with_version(2, v2_end).as_slice()).unwrap();
+        assert_same_fields(&v2.fluid, &fluid);
+        assert_eq!(v2.fluid.boundaries, fluid.boundaries);
+        assert_eq!(v2.fluid.obstacles, fluid.obstacles);
+        assert!(v2.fluid.emitters.is_empty() && v2.fluid.sinks.is_empty());
+    }
+
+    #[test]
+    fn corrupt_snapshots_are_rejected() {
+        let fluid = sample();
+        let bytes = write(&fluid);
+
+        let mut unknown_version = bytes.clone();
+        unknown_version[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let mut huge = bytes.clone();
+        huge[8..16].copy_from_slice(&(1u64 << 40).to_le_bytes());
+
+        for bytes in [&bytes[..fields_end(&fluid) - 1], &unknown_version, &huge, b"NOPE"] {
+            assert!(Snapshot::read(&mut &bytes[..]).is_err());
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use super::fluid_sim::FluidSim;
+
+/// Passive Lagrangian particles carried by the velocity field.
+///
+/// Positions are in grid cells, the same space `FluidSim` advects in. Once
+/// `max_particles` is reached the oldest particles make room for new ones.
+pub struct Tracers {
+    pub particles: VecDeque<(f64, f64)>,
+    pub max_particles: usize,
+}
+
+impl Tracers {
+    pub fn new(max_particles: usize) -> Self {
+        Tracers {
+            particles: VecDeque::new(),
+            max_particles,
+        }
+    }
+
+    fn push(&mut self, x: f64, y: f64) {
+        if self.particles.len() >= self.max_particles {
+            self.particles.pop_front();
+        }
+        self.particles.push_back((x, y));
+    }
+
+    /// Seeds `count` particles uniformly in a disc around a grid position.
+    pub fn seed_at(&mut self, x: f64, y: f64, radius: f64, count: usize) {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..count {
+            let r = radius * rng.gen_range(0.0f64..1.0).sqrt();
+            let a = rng.gen_range(0.0..std::f64::consts::TAU);
+            self.push(x + r * a.cos(), y + r * a.sin());
+        }
+    }
+
+    /// Seeds `count` particles evenly spaced along a segment.
+    pub fn seed_line(&mut self, from: (f64, f64), to: (f64, f64), count: usize) {
+        for i in 0..count {
+            let t = if count > 1 { i as f64 / (count - 1) as f64 } else { 0.5 };
+            self.push(from.0 + t * (to.0 - from.0), from.1 + t * (to.1 - from.1));
+        }
+    }
+
+    /// Moves particles onto a resized grid, keeping their place relative to the domain.
+    pub fn rescale(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let sx = to.0 as f64 / from.0 as f64;
+        let sy = to.1 as f64 / from.1 as f64;
+        for (x, y) in self.particles.iter_mut() {
+            *x = (*x + 0.5) * sx - 0.5;
+            *y = (*y + 0.5) * sy - 0.5;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Moves every particle with a midpoint (RK2) step and drops those leaving the domain.
+    pub fn advect(&mut self, fluid: &FluidSim, dt: f64) {
+        let max_x = (fluid.width - 1) as f64;
+        let max_y = (fluid.height - 1) as f64;
+
+        self.particles.retain_mut(|(x, y)| {
+            let (vx, vy) = fluid.sample_velocity(*x, *y);
+            let (mx, my) = (*x + 0.5 * dt * vx, *y + 0.5 * dt * vy);
+            let (vx, vy) = fluid.sample_velocity(mx, my);
+            *x += dt * vx;
+            *y += dt * vy;
+
+            *x >= 0.0 && *y >= 0.0 && *x <= max_x && *y <= max_y
+        });
+    }
+}
@@ -0,0 +1,206 @@
+//! Checks of the solver against flows with a known analytic solution.
+//!
+//! Tolerances leave room for the discretization error of the scheme at the
+//! resolutions used here, a regression typically misses them by far.
+
+use std::f64::consts::PI;
+
+use super::fluid_sim::FluidSim;
+
+/// Fills a field from a function of the cell position.
+fn fill(fluid: &FluidSim, f: impl Fn(f64, f64) -> f64) -> Vec<f64> {
+    let mut field = vec![0.0; fluid.width * fluid.height];
+    for y in 0..fluid.height {
+        for x in 0..fluid.width {
+            field[x + y * fluid.width] = f(x as f64, y as f64);
+        }
+    }
+    field
+}
+
+fn max_abs_difference(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max)
+}
+
+fn max_abs(field: &[f64]) -> f64 {
+    field.iter().map(|v| v.abs()).fold(0.0, f64::max)
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+/// Twice the kinetic energy of the whole grid.
+fn energy(fluid: &FluidSim) -> f64 {
+    fluid.velocity_x.iter().zip(&fluid.velocity_y).map(|(u, v)| u * u + v * v).sum()
+}
+
+/// Center of mass of the density.
+fn centroid(fluid: &FluidSim) -> (f64, f64) {
+    let (mut mass, mut cx, mut cy) = (0.0, 0.0, 0.0);
+    for y in 0..fluid.height {
+        for x in 0..fluid.width {
+            let d = fluid.get_density(x, y);
+            mass += d;
+            cx += d * x as f64;
+            cy += d * y as f64;
+        }
+    }
+    (cx / mass, cy / mass)
+}
+
+/// Variance of the density along each axis around its center of mass, averaged.
+fn variance(fluid: &FluidSim) -> f64 {
+    let (cx, cy) = centroid(fluid);
+    let (mut mass, mut moment) = (0.0, 0.0);
+    for y in 0..fluid.height {
+        for x in 0..fluid.width {
+            let d = fluid.get_density(x, y);
+            mass += d;
+            moment += d * ((x as f64 - cx).powi(2) + (y as f64 - cy).powi(2));
+        }
+    }
+    0.5 * moment / mass
+}
+
+fn gaussian(x: f64, y: f64, center: (f64, f64), variance: f64) -> f64 {
+    (-((x - center.0).powi(2) + (y - center.1).powi(2)) / (2.0 * variance)).exp()
+}
+
+#[test]
+fn diffusion_of_a_gaussian_follows_the_heat_kernel() {
+    let size = 64;
+    let (diffusion_rate, dt, steps) = (1.0, 0.005, 1000);
+    let center = (size as f64 / 2.0, size as f64 / 2.0);
+    let start_variance = 4.0;
+
+    let mut fluid = FluidSim::new(size, size, 0.0, diffusion_rate);
+    fluid.density = fill(&fluid, |x, y| gaussian(x, y, center, start_variance));
+    let start_mass = fluid.total_mass();
+
+    for _ in 0..steps {
+        fluid.diffuse_density(dt, 1);
+    }
+
+    // A Gaussian stays a Gaussian, its variance growing by 2 D t and its peak falling to keep the mass
+    let variance_now = start_variance + 2.0 * diffusion_rate * dt * steps as f64;
+    let expected = fill(&fluid, |x, y| start_variance / variance_now * gaussian(x, y, center, variance_now));
+
+    // Each pass diffuses at D / (1 + 4 D dt), close to D for small steps
+    let variance_error = (variance(&fluid) - variance_now).abs() / variance_now;
+    assert!(variance_error < 0.03, "variance off by {:.2}%", variance_error * 100.0);
+    let error = max_abs_difference(&fluid.density, &expected) / max_abs(&expected);
+    assert!(error < 0.05, "density off by {:.2}% of the peak", error * 100.0);
+    assert!((fluid.total_mass() - start_mass).abs() < 1e-9 * start_mass, "diffusion changed the mass");
+}
+
+#[test]
+fn projection_removes_the_divergence() {
+    let size = 48;
+    let center = (size as f64 / 2.0, size as f64 / 2.0);
+    let spread = 36.0;
+
+    // A gradient flow, all divergence, plus a rotational flow, divergence free
+    let potential = |x: f64, y: f64| 20.0 * gaussian(x, y, center, spread);
+    let stream = |x: f64, y: f64| 20.0 * gaussian(x, y, (center.0 + 4.0, center.1 - 3.0), spread);
+    let derivative = |f: &dyn Fn(f64, f64) -> f64, x: f64, y: f64, (dx, dy): (f64, f64)| {
+        let h = 1e-4;
+        (f(x + dx * h, y + dy * h) - f(x - dx * h, y - dy * h)) / (2.0 * h)
+    };
+
+    let mut fluid = FluidSim::new(size, size, 0.0, 0.0);
+    let rotational_x = fill(&fluid, |x, y| derivative(&stream, x, y, (0.0, 1.0)));
+    let rotational_y = fill(&fluid, |x, y| -derivative(&stream, x, y, (1.0, 0.0)));
+    let gradient_x = fill(&fluid, |x, y| derivative(&potential, x, y, (1.0, 0.0)));
+    let gradient_y = fill(&fluid, |x, y| derivative(&potential, x, y, (0.0, 1.0)));
+    fluid.velocity_x = rotational_x.iter().zip(&gradient_x).map(|(r, g)| r + g).collect();
+    fluid.velocity_y = rotational_y.iter().zip(&gradient_y).map(|(r, g)| r + g).collect();
+
+    let divergence_before = fluid.max_velocity_divergence();
+    fluid.enforce_incompressibility(2000);
+    let divergence_after = fluid.max_velocity_divergence();
+
+    // The pressure equation and the central differences of the correction don't use
+    // the same stencil, so only the smooth part of the divergence goes away
+    assert!(divergence_after < 0.05 * divergence_before,
+        "max divergence only went from {divergence_before:.4} to {divergence_after:.4}");
+
+    // What is left is the rotational part of the flow
+    let error = max_abs_difference(&fluid.velocity_x, &rotational_x)
+        .max(max_abs_difference(&fluid.velocity_y, &rotational_y));
+    let scale = max_abs(&rotational_x).max(max_abs(&rotational_y));
+    assert!(error < 0.05 * scale, "rotational flow off by {error:.4} for a speed of {scale:.4}");
+}
+
+#[test]
+fn rigid_rotation_carries_the_density_around() {
+    let size = 64;
+    let center = (size as f64 / 2.0, size as f64 / 2.0);
+    let (angular_speed, dt) = (0.5, 0.05);
+    let steps_per_turn = (2.0 * PI / angular_speed / dt).round() as usize;
+    let start = (center.0 + 12.0, center.1);
+
+    let mut fluid = FluidSim::new(size, size, 0.0, 0.0);
+    fluid.velocity_x = fill(&fluid, |_, y| -angular_speed * (y - center.1));
+    fluid.velocity_y = fill(&fluid, |x, _| angular_speed * (x - center.0));
+    fluid.density = fill(&fluid, |x, y| gaussian(x, y, start, 9.0));
+    let start_mass = fluid.total_mass();
+
+    for _ in 0..steps_per_turn / 4 {
+        fluid.advect_density(dt);
+    }
+    // y points down, so the blob turns from the right of the center to below it
+    let quarter = (center.0, center.1 + 12.0);
+    assert!(distance(centroid(&fluid), quarter) < 1.0, "after a quarter turn the blob is at {:?}", centroid(&fluid));
+
+    for _ in steps_per_turn / 4..steps_per_turn {
+        fluid.advect_density(dt);
+    }
+    assert!(distance(centroid(&fluid), start) < 1.5, "after a turn the blob is at {:?}", centroid(&fluid));
+
+    // Backtracing in a straight line slightly stretches the rotation, and bilinear
+    // sampling isn't conservative, so some dye is lost on the way
+    let mass = fluid.total_mass();
+    assert!(mass > 0.8 * start_mass && mass <= start_mass, "mass went from {start_mass:.3} to {mass:.3}");
+}
+
+/// Runs a Taylor-Green vortex filling a walled square of `size` cells at a speed
+/// proportional to the grid, returning its energy after each step relative to the
+/// start, and the correlation of the final velocity with the initial one.
+fn taylor_green(size: usize, steps: usize) -> (Vec<f64>, f64) {
+    let k = PI / (size - 1) as f64;
+    let speed = size as f64 / 16.0;
+
+    let mut fluid = FluidSim::new(size, size, 0.0, 0.0);
+    fluid.velocity_x = fill(&fluid, |x, y| speed * (k * x).sin() * (k * y).cos());
+    fluid.velocity_y = fill(&fluid, |x, y| -speed * (k * x).cos() * (k * y).sin());
+    let (start_x, start_y) = (fluid.velocity_x.clone(), fluid.velocity_y.clone());
+    let start_energy = energy(&fluid);
+
+    let mut energies = Vec::with_capacity(steps);
+    for _ in 0..steps {
+        fluid.step(0.05, 40, 0);
+        energies.push(energy(&fluid) / start_energy);
+    }
+
+    let overlap: f64 = fluid.velocity_x.iter().zip(&start_x).map(|(a, b)| a * b).sum::<f64>() +
+        fluid.velocity_y.iter().zip(&start_y).map(|(a, b)| a * b).sum::<f64>();
+    (energies, overlap / (energy(&fluid) * start_energy).sqrt())
+}
+
+/// Without viscosity the Taylor-Green vortex is a steady flow, so all of its decay is
+/// numerical dissipation: it must never add energy, keep the shape of the vortex, and
+/// shrink as the grid is refined.
+#[test]
+fn taylor_green_vortex_only_decays_numerically() {
+    let (coarse, _) = taylor_green(33, 100);
+    let (fine, correlation) = taylor_green(65, 100);
+
+    assert!(fine.windows(2).all(|w| w[1] <= w[0]), "the vortex gained energy");
+    assert!(correlation > 0.9, "the vortex lost its shape, correlation {correlation:.3}");
+
+    let (coarse, fine) = (coarse[coarse.len() - 1], fine[fine.len() - 1]);
+    assert!(fine > 0.75, "the vortex kept only {:.1}% of its energy", fine * 100.0);
+    assert!(1.0 - fine < 0.8 * (1.0 - coarse),
+        "refining the grid barely reduced the dissipation: {:.3} vs {:.3}", 1.0 - fine, 1.0 - coarse);
+}
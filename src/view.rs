@@ -0,0 +1,105 @@
+/// Maps between screen pixels and grid coordinates, with a zoom and pan camera.
+///
+/// Grid coordinates put cell centers on integers, matching how `FluidSim`
+/// samples its fields, so cell `i` covers `[i - 0.5, i + 0.5)`. At zoom 1 the
+/// grid is stretched over the whole screen, the two sizes don't need to divide evenly.
+#[derive(Clone, Copy, Debug)]
+pub struct ViewTransform {
+    pub screen_width: usize,
+    pub screen_height: usize,
+    pub grid_width: usize,
+    pub grid_height: usize,
+    /// Magnification over the fit-to-screen view, always >= 1.
+    pub zoom: f64,
+    /// Point of the grid shown at the center of the screen, in grid edge units
+    /// (0 is the left/top edge of the grid, `grid_width` the right edge).
+    pub center: (f64, f64),
+}
+
+pub const MAX_ZOOM: f64 = 32.0;
+
+impl ViewTransform {
+    pub fn new(screen_width: usize, screen_height: usize, grid_width: usize, grid_height: usize) -> Self {
+        ViewTransform {
+            screen_width,
+            screen_height,
+            grid_width,
+            grid_height,
+            zoom: 1.0,
+            center: (grid_width as f64 / 2.0, grid_height as f64 / 2.0),
+        }
+    }
+
+    /// Grid size for a screen when each cell should cover about `precision` pixels.
+    pub fn grid_size(screen_width: usize, screen_height: usize, precision: usize) -> (usize, usize) {
+        let cells = |pixels: usize| ((pixels as f64 / precision.max(1) as f64).round() as usize).max(3);
+        (cells(screen_width), cells(screen_height))
+    }
+
+    /// Adapts to new screen and grid sizes, keeping the zoom and the relative camera position.
+    pub fn resize(&mut self, screen_width: usize, screen_height: usize, grid_width: usize, grid_height: usize) {
+        self.center.0 *= grid_width as f64 / self.grid_width as f64;
+        self.center.1 *= grid_height as f64 / self.grid_height as f64;
+        self.screen_width = screen_width;
+        self.screen_height = screen_height;
+        self.grid_width = grid_width;
+        self.grid_height = grid_height;
+        self.clamp_center();
+    }
+
+    /// Screen pixels per grid cell along each axis.
+    pub fn pixels_per_cell(&self) -> (f64, f64) {
+        (
+            self.zoom * self.screen_width as f64 / self.grid_width as f64,
+            self.zoom * self.screen_height as f64 / self.grid_height as f64,
+        )
+    }
+
+    /// Converts a continuous screen position (pixel edges on integers) to grid coordinates.
+    pub fn screen_to_grid(&self, sx: f64, sy: f64) -> (f64, f64) {
+        let (px, py) = self.pixels_per_cell();
+        (
+            (sx - self.screen_width as f64 / 2.0) / px + self.center.0 - 0.5,
+            (sy - self.screen_height as f64 / 2.0) / py + self.center.1 - 0.5,
+        )
+    }
+
+    pub fn grid_to_screen(&self, gx: f64, gy: f64) -> (f64, f64) {
+        let (px, py) = self.pixels_per_cell();
+        (
+            (gx + 0.5 - self.center.0) * px + self.screen_width as f64 / 2.0,
+            (gy + 0.5 - self.center.1) * py + self.screen_height as f64 / 2.0,
+        )
+    }
+
+    /// Multiplies the zoom by `factor` while keeping the grid point under `(sx, sy)` in place.
+    pub fn zoom_at(&mut self, sx: f64, sy: f64, factor: f64) {
+        let before = self.screen_to_grid(sx, sy);
+        self.zoom = (self.zoom * factor).clamp(1.0, MAX_ZOOM);
+        let after = self.screen_to_grid(sx, sy);
+        self.center.0 += before.0 - after.0;
+        self.center.1 += before.1 - after.1;
+        self.clamp_center();
+    }
+
+    /// Moves the camera so the grid follows a drag of `(dx, dy)` screen pixels.
+    pub fn pan_by(&mut self, dx: f64, dy: f64) {
+        let (px, py) = self.pixels_per_cell();
+        self.center.0 -= dx / px;
+        self.center.1 -= dy / py;
+        self.clamp_center();
+    }
+
+    pub fn reset(&mut self) {
+        self.zoom = 1.0;
+        self.center = (self.grid_width as f64 / 2.0, self.grid_height as f64 / 2.0);
+    }
+
+    /// Keeps the visible area inside the grid.
+    fn clamp_center(&mut self) {
+        let half_x = self.grid_width as f64 / (2.0 * self.zoom);
+        let half_y = self.grid_height as f64 / (2.0 * self.zoom);
+        self.center.0 = self.center.0.clamp(half_x, self.grid_width as f64 - half_x);
+        self.center.1 = self.center.1.clamp(half_y, self.grid_height as f64 - half_y);
+    }
+}
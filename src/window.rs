@@ -1,194 +1,988 @@
-use minifb::{Window, WindowOptions};
-
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::export::{self, DiagnosticsLog, FieldExporter, FieldFormat, FrameRecorder, LogFormat, RecordFormat};
+use crate::input::{FrameInput, Key, Stroke};
+use crate::replay::{self, Clock, InputRecorder, InputReplay, LiveParams};
+use crate::render::{self, overlay, Colormap, ColormapKind, Lic, RenderField, TrailLayer};
+use crate::settings::SimulationSettings;
+use crate::simulation::brush::{Brush, BrushShape};
+use crate::simulation::diagnostics::FlowStats;
+use crate::simulation::emitter::{Emitter, Sink};
 use crate::simulation::fluid_sim;
+use crate::simulation::scenario::Scenario;
+use crate::simulation::snapshot::Snapshot;
+use crate::simulation::tracers::Tracers;
+use crate::view::ViewTransform;
+
+const SNAPSHOT_DIR: &str = "snapshots";
 
+/// Longest time step the simulation takes, longer frames are slowed down for stability.
+pub const MAX_DT: f64 = 0.05;
 
+/// The live simulation: owns the fluid, turns user input into forces and renders
+/// into a `0x00RRGGBB` pixel buffer that the app shows next to its settings panel.
 pub struct FluidWindow {
     pub width: usize,
     pub height: usize,
     pub particle_radius: usize,
+    pub brush_shape: BrushShape,
+    pub brush_strength: f64,
+    pub brush_force: f64,
+    pub eraser: bool,
     pub precision : usize,
-    pub window: Window,
-    pub start_density: f64,
     pub diffusion_rate: f64,
-    pub max_color: u32,
-    pub randomize: bool,
-    pub random_smoothing: usize,
+    pub colormap_kind: ColormapKind,
+    pub gradient: Vec<u32>,
+    pub field: RenderField,
+    pub show_velocity: bool,
+    /// Statistics of the flow and the solver drawn over the view.
+    pub show_stats: bool,
+    /// Whether frames are drawn into the buffer, headless runs only draw the frames they record.
+    pub draw: bool,
+    pub show_trails: bool,
+    pub use_lic: bool,
+    pub lic_modulate: bool,
+    pub smooth: bool,
     pub pressure_iters: usize,
     pub diffusion_iters: usize,
+    pub conserve_mass: bool,
+    /// Measures every step as `FluidSim::measure` does even when neither the statistics
+    /// nor a diagnostics log need it.
+    pub measure: bool,
+    pub record_stride: usize,
+    pub record_grid: bool,
+    pub record_format: RecordFormat,
+    pub record_scale: f64,
+    pub field_format: FieldFormat,
+    pub field_stride: usize,
+    pub log_format: LogFormat,
+    pub emitter_density: f64,
+    pub emitter_speed: f64,
+    pub emitter_angle: f64,
+    pub sink_rate: f64,
+    /// Simulated time in seconds since launch.
+    pub time: f64,
+    pub fluid: fluid_sim::FluidSim,
+    pub view: ViewTransform,
     buffer: Vec<u32>,
+    values: Vec<f64>,
+    colormap: Colormap,
+    tracers: Tracers,
+    trails: TrailLayer,
+    lic: Lic,
+    lic_values: Vec<f64>,
     fps: f64,
     frame_count: usize,
     last_fps_update: std::time::Instant,
+    last_mouse: Option<(f64, f64)>,
+    /// Grid position of the cursor at the last frame, where strokes continue from even
+    /// if the view moved since.
+    last_cell: Option<(f64, f64)>,
     paused: bool,
     step_frame: usize,
+    message: Option<(String, std::time::Instant)>,
+    recorder: Option<FrameRecorder>,
+    exporter: Option<FieldExporter>,
+    diagnostics: Option<DiagnosticsLog>,
+    input_recorder: Option<InputRecorder>,
+    replay: Option<InputReplay>,
+    scenario: Option<Scenario>,
+    /// Whether the grid came from a snapshot, scenario or replay, it then stays as it is
+    /// whatever the size of the view.
+    fixed_grid: bool,
 }
 
 impl FluidWindow {
-    pub fn new(width: usize, height: usize, particle_radius: usize, precision: usize, start_density: f64, diffusion_rate: f64, max_color: u32, randomize: bool, 
-        random_smoothing: usize, pressure_iters: usize, diffusion_iters: usize) -> Self {
+    pub fn new(settings: &SimulationSettings) -> Self {
+        let width = settings.width;
+        let height = settings.height;
+        let gradient = settings.gradient_stops();
+
+        let (grid_width, grid_height) = ViewTransform::grid_size(width, height, settings.precision);
+        let mut fluid = fluid_sim::FluidSim::new(
+            grid_width,
+            grid_height,
+            settings.start_density,
+            settings.diffusion_rate,
+        );
+
+        if settings.randomize {
+            fluid.randomize_density_smoothed(settings.random_smoothing);
+        }
+
         FluidWindow {
             width,
             height,
-            particle_radius,
-            precision,
-            window: Window::new(
-                "Fluid Simulation", 
-                width,
-                height,
-                WindowOptions {
-                    resize: false,
-                    ..WindowOptions::default()
-                })
-                .unwrap_or_else(|e| {
-                    panic!("Unable to create window: {}", e);
-                }),
-            start_density,
-            diffusion_rate,
-            max_color,
-            randomize,
-            random_smoothing,
-            pressure_iters,
-            diffusion_iters,
+            particle_radius: settings.particle_radius,
+            brush_shape: settings.brush_shape,
+            brush_strength: settings.brush_strength,
+            brush_force: settings.brush_force,
+            eraser: settings.eraser,
+            precision: settings.precision,
+            diffusion_rate: settings.diffusion_rate,
+            colormap: Colormap::new(settings.colormap, &gradient),
+            colormap_kind: settings.colormap,
+            gradient,
+            field: settings.field,
+            show_velocity: false,
+            show_stats: false,
+            draw: true,
+            show_trails: true,
+            use_lic: settings.lic,
+            lic_modulate: settings.lic_modulate,
+            smooth: settings.smooth,
+            pressure_iters: settings.pressure_iters,
+            diffusion_iters: settings.diffusion_iters,
+            conserve_mass: settings.conserve_mass,
+            measure: false,
+            record_stride: settings.record_stride,
+            record_grid: settings.record_grid,
+            record_format: settings.record_format,
+            record_scale: settings.record_scale,
+            field_format: settings.field_format,
+            field_stride: settings.field_stride,
+            log_format: settings.log_format,
+            emitter_density: settings.emitter_density,
+            emitter_speed: settings.emitter_speed,
+            emitter_angle: settings.emitter_angle,
+            sink_rate: settings.sink_rate,
+            time: 0.0,
+            fluid,
+            view: ViewTransform::new(width, height, grid_width, grid_height),
             buffer: vec![0u32; width * height],
+            values: Vec::new(),
+            tracers: Tracers::new(5000),
+            trails: TrailLayer::new(0.95),
+            lic: Lic::new(width, height, 15),
+            lic_values: Vec::new(),
             fps: 0.0,
             frame_count: 0,
             last_fps_update: std::time::Instant::now(),
+            last_mouse: None,
+            last_cell: None,
             paused: false,
             step_frame: 0,
+            message: None,
+            recorder: None,
+            exporter: None,
+            diagnostics: None,
+            input_recorder: None,
+            replay: None,
+            scenario: None,
+            fixed_grid: false,
         }
     }
 
-    pub fn run(&mut self) {
-        let mut fluid = fluid_sim::FluidSim::new(
-            (self.width / self.precision) as usize,
-            (self.height / self.precision) as usize,
-            self.start_density,
-            self.diffusion_rate,
-        );
+    /// The last rendered frame, `width * height` pixels packed as `0x00RRGGBB`.
+    pub fn buffer(&self) -> &[u32] {
+        &self.buffer
+    }
 
-        if self.randomize {
-            fluid.randomize_density_smoothed(self.random_smoothing);
+    /// One line summary of the simulation state, for the app to display.
+    pub fn status(&self) -> String {
+        let mut status = format!("FPS: {:.1} ({}) - {} / {}", self.fps, if self.paused { "PAUSED" } else { "RUNNING" },
+            self.field.name(), self.colormap_kind.name());
+        if let Some(scenario) = &self.scenario {
+            status.push_str(&format!(" - {} t={:.2}s", scenario.name, self.time));
         }
+        if self.conserve_mass || self.fluid.measure {
+            let mass = &self.fluid.mass;
+            status.push_str(&format!(" - Mass: {:.2} (drift {:+.2e}/step{})", mass.end, mass.drift,
+                if self.conserve_mass { ", corrected" } else { "" }));
+        }
+        if self.eraser {
+            status.push_str(" - ERASER");
+        }
+        if let Some(recorder) = &self.recorder {
+            status.push_str(&format!(" - REC {} frames", recorder.written));
+        }
+        if let Some(exporter) = &self.exporter {
+            status.push_str(&format!(" - EXPORT {} steps", exporter.written));
+        }
+        if let Some(log) = &self.diagnostics {
+            status.push_str(&format!(" - LOG {} steps", log.written));
+        }
+        if let Some(recorder) = &self.input_recorder {
+            status.push_str(&format!(" - INPUT {} frames", recorder.frame));
+        }
+        if let Some(replay) = &self.replay {
+            status.push_str(&format!(" - REPLAY {}/{}", replay.frame, replay.length));
+        }
+        if let Some((message, _)) = &self.message {
+            status.push_str(" - ");
+            status.push_str(message);
+        }
+        status
+    }
 
-        let mut last_mouse = (0usize, 0usize);
-        let mut last_time = std::time::Instant::now();
+    /// Shows a message in the status line for a few seconds.
+    fn notify(&mut self, message: String) {
+        self.message = Some((message, std::time::Instant::now()));
+    }
 
-        while self.window.is_open() && !self.window.is_key_down(minifb::Key::Escape) {
-            let now = std::time::Instant::now();
-            let dt = (now - last_time).as_secs_f64();
-            last_time = now;
+    pub fn save_snapshot(&mut self) {
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let path = Path::new(SNAPSHOT_DIR).join(format!("snapshot_{stamp}.fsim"));
 
-            if dt <= 0.0 {
-                continue;
-            }
+        match Snapshot::save(&path, &self.fluid, self.pressure_iters, self.diffusion_iters) {
+            Ok(()) => self.notify(format!("Saved {}", path.display())),
+            Err(e) => self.notify(format!("Unable to save snapshot: {e}")),
+        }
+    }
 
-            self.frame_count += 1;
-            let elapsed = now.duration_since(self.last_fps_update).as_secs_f64();
-            if elapsed >= 0.5 {
-                self.fps = self.frame_count as f64 / elapsed;
-                self.frame_count = 0;
-                self.last_fps_update = now;
-                let title = format!("Fluid Simulation - FPS: {:.1} ({})", self.fps, if self.paused { "PAUSED" } else { "RUNNING" });
-                self.window.set_title(&title);
-            }
+    /// Restores the most recent snapshot of the snapshot directory.
+    pub fn load_latest_snapshot(&mut self) {
+        let Some(path) = latest_snapshot() else {
+            self.notify(format!("No snapshot found in {SNAPSHOT_DIR}/"));
+            return;
+        };
 
-            if self.window.is_key_pressed(minifb::Key::Space, minifb::KeyRepeat::No) {
-                self.paused = !self.paused;
-            }
+        match self.load_snapshot(&path) {
+            Ok(()) => self.notify(format!("Loaded {}", path.display())),
+            Err(e) => self.notify(format!("Unable to load {}: {e}", path.display())),
+        }
+    }
 
-            if self.paused {
-                if self.window.is_key_pressed(minifb::Key::Right, minifb::KeyRepeat::No) {
-                    self.step_frame = 1;
-                }
-                if self.window.is_key_pressed(minifb::Key::Up, minifb::KeyRepeat::No) {
-                    self.step_frame = 10;
-                }
-            }
+    pub fn load_snapshot(&mut self, path: &Path) -> io::Result<()> {
+        self.restore(Snapshot::load(path)?);
+        Ok(())
+    }
 
-            let (mx, my) = self
-                .window
-                .get_mouse_pos(minifb::MouseMode::Clamp)
-                .unwrap_or((0.0, 0.0));
+    fn restore(&mut self, snapshot: Snapshot) {
+        if self.input_recorder.is_some() {
+            self.stop_input_recording();
+        }
+        self.tracers.clear();
+        self.trails.clear();
+        self.fluid = snapshot.fluid;
+        self.diffusion_rate = self.fluid.diffusion_rate;
+        self.pressure_iters = snapshot.pressure_iters;
+        self.diffusion_iters = snapshot.diffusion_iters;
+        self.view = ViewTransform::new(self.width, self.height, self.fluid.width, self.fluid.height);
+        self.fixed_grid = true;
+    }
 
-            let mx = mx as usize;
-            let my = my as usize;
+    /// Restarts the simulation on the domain of a scenario, which then drives its
+    /// emitters and forces as time goes.
+    pub fn load_scenario(&mut self, path: &Path) -> io::Result<()> {
+        let scenario = Scenario::load(path)?;
+        if self.input_recorder.is_some() {
+            self.stop_input_recording();
+        }
 
-            let fx = (mx as f64 - last_mouse.0 as f64) / dt;
-            let fy = (my as f64 - last_mouse.1 as f64) / dt;
+        self.tracers.clear();
+        self.trails.clear();
+        self.fluid = scenario.build(self.diffusion_rate);
+        self.fixed_grid = true;
+        self.diffusion_rate = self.fluid.diffusion_rate;
+        self.pressure_iters = scenario.solver.pressure_iters.unwrap_or(self.pressure_iters);
+        self.diffusion_iters = scenario.solver.diffusion_iters.unwrap_or(self.diffusion_iters);
+        self.conserve_mass = scenario.solver.conserve_mass.unwrap_or(self.conserve_mass);
+        self.view = ViewTransform::new(self.width, self.height, self.fluid.width, self.fluid.height);
+        self.time = 0.0;
+
+        self.notify(format!("Loaded scenario {}", path.display()));
+        self.scenario = Some(scenario);
+        Ok(())
+    }
 
-            let gx = mx / self.precision;
-            let gy = my / self.precision;
+    pub fn scenario(&self) -> Option<&Scenario> {
+        self.scenario.as_ref()
+    }
 
-            if gx > 1 && gx < fluid.width - 1 && gy > 1 && gy < fluid.height - 1 {
-                let r = self.particle_radius / self.precision.max(1);
+    /// Applies the settings that make sense on a running simulation, the grid
+    /// size and initial conditions are only used at launch.
+    pub fn apply_live_settings(&mut self, settings: &SimulationSettings) {
+        self.diffusion_rate = settings.diffusion_rate;
+        self.fluid.diffusion_rate = settings.diffusion_rate;
+        self.pressure_iters = settings.pressure_iters;
+        self.diffusion_iters = settings.diffusion_iters;
+        self.conserve_mass = settings.conserve_mass;
+        self.particle_radius = settings.particle_radius;
+        self.brush_shape = settings.brush_shape;
+        self.brush_strength = settings.brush_strength;
+        self.brush_force = settings.brush_force;
+        self.eraser = settings.eraser;
+        self.field = settings.field;
+        self.smooth = settings.smooth;
+        self.use_lic = settings.lic;
+        self.lic_modulate = settings.lic_modulate;
+        self.colormap_kind = settings.colormap;
+        self.gradient = settings.gradient_stops();
+        self.colormap = Colormap::new(self.colormap_kind, &self.gradient);
+        self.record_stride = settings.record_stride;
+        self.record_grid = settings.record_grid;
+        self.record_format = settings.record_format;
+        self.record_scale = settings.record_scale;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.stride = settings.record_stride.max(1);
+        }
+        self.field_format = settings.field_format;
+        self.field_stride = settings.field_stride;
+        self.log_format = settings.log_format;
+        self.emitter_density = settings.emitter_density;
+        self.emitter_speed = settings.emitter_speed;
+        self.emitter_angle = settings.emitter_angle;
+        self.sink_rate = settings.sink_rate;
+        if let Some(exporter) = &mut self.exporter {
+            exporter.stride = settings.field_stride.max(1);
+        }
+    }
 
-                for dx in -(r as isize)..=(r as isize) {
-                    for dy in -(r as isize)..=(r as isize) {
-                        let x = gx as isize + dx;
-                        let y = gy as isize + dy;
+    /// Writes the live settings back into `settings`, the counterpart of `apply_live_settings`
+    /// for what hotkeys, scenarios, snapshots and replays changed on the window.
+    /// The custom gradient is left alone, nothing but the settings changes it.
+    pub fn store_live_settings(&self, settings: &mut SimulationSettings) {
+        settings.diffusion_rate = self.diffusion_rate;
+        settings.pressure_iters = self.pressure_iters;
+        settings.diffusion_iters = self.diffusion_iters;
+        settings.conserve_mass = self.conserve_mass;
+        settings.particle_radius = self.particle_radius;
+        settings.brush_shape = self.brush_shape;
+        settings.brush_strength = self.brush_strength;
+        settings.brush_force = self.brush_force;
+        settings.eraser = self.eraser;
+        settings.field = self.field;
+        settings.smooth = self.smooth;
+        settings.lic = self.use_lic;
+        settings.lic_modulate = self.lic_modulate;
+        settings.colormap = self.colormap_kind;
+        settings.record_stride = self.record_stride;
+        settings.record_grid = self.record_grid;
+        settings.record_format = self.record_format;
+        settings.record_scale = self.record_scale;
+        settings.field_format = self.field_format;
+        settings.field_stride = self.field_stride;
+        settings.log_format = self.log_format;
+        settings.emitter_density = self.emitter_density;
+        settings.emitter_speed = self.emitter_speed;
+        settings.emitter_angle = self.emitter_angle;
+        settings.sink_rate = self.sink_rate;
+    }
 
-                        if x <= 0 || y <= 0 ||
-                        x >= fluid.width as isize - 1 ||
-                        y >= fluid.height as isize - 1 {
-                            continue;
-                        }
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
 
-                        let x = x as usize;
-                        let y = y as usize;
+    /// Starts recording to `path`, or to a fresh file of the output directory.
+    pub fn start_recording(&mut self, path: Option<PathBuf>) -> io::Result<()> {
+        let path = path.unwrap_or_else(|| {
+            let prefix = if self.record_format == RecordFormat::Png { "frames" } else { "recording" };
+            export::timestamped_path(prefix, self.record_format.extension())
+        });
+
+        let recorder = FrameRecorder::start(path, self.record_format, self.record_stride, self.record_scale)?;
+        self.notify(format!("Recording to {}", recorder.path.display()));
+        self.recorder = Some(recorder);
+        Ok(())
+    }
 
-                        if self.window.get_mouse_down(minifb::MouseButton::Left) {
-                            let idx = x + y * fluid.width;
-                            fluid.density[idx] = (fluid.density[idx] + 2.0 * dt).min(1.0);
-                        }
+    /// Finishes the recording, returning the number of frames written and where.
+    pub fn stop_recording(&mut self) -> io::Result<Option<(usize, PathBuf)>> {
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(None);
+        };
+        let (written, path) = (recorder.written, recorder.path.clone());
+        recorder.finish()?;
+        self.notify(format!("Wrote {written} frames to {}", path.display()));
+        Ok(Some((written, path)))
+    }
 
-                        if self.window.get_mouse_down(minifb::MouseButton::Right) {
-                            let idx = x + y * fluid.width;
-                            fluid.velocity_x[idx] += fx * 0.05;
-                            fluid.velocity_y[idx] += fy * 0.05;
-                        }
-                    }
+    fn toggle_recording(&mut self) {
+        let result = if self.is_recording() {
+            self.stop_recording().map(|_| ())
+        } else {
+            self.start_recording(None)
+        };
+        if let Err(e) = result {
+            self.notify(format!("Recording failed: {e}"));
+        }
+    }
+
+    /// Hands the current frame to the recorder, either as shown or as the raw field.
+    fn record_frame(&mut self) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+
+        let result = if self.record_grid {
+            self.field.sample(&self.fluid, &mut self.values);
+            let pixels: Vec<u32> = self.values.iter().map(|&v| self.colormap.map(v)).collect();
+            recorder.capture(self.fluid.width, self.fluid.height, &pixels)
+        } else {
+            recorder.capture(self.width, self.height, &self.buffer)
+        };
+
+        if let Err(e) = result {
+            self.recorder = None;
+            self.notify(format!("Recording stopped: {e}"));
+        }
+    }
+
+    /// Writes the fields of the current step to a fresh directory of the output directory.
+    pub fn export_fields(&mut self) {
+        let result = FieldExporter::start(export::timestamped_path("fields", None), self.field_format, 1)
+            .and_then(|mut exporter| {
+                exporter.capture(&self.fluid, self.time)?;
+                let path = exporter.path.clone();
+                exporter.finish()?;
+                Ok(path)
+            });
+
+        match result {
+            Ok(path) => self.notify(format!("Exported fields to {}", path.display())),
+            Err(e) => self.notify(format!("Field export failed: {e}")),
+        }
+    }
+
+    pub fn is_exporting(&self) -> bool {
+        self.exporter.is_some()
+    }
+
+    /// Starts exporting the fields of every `field_stride`-th step to `path`, or to
+    /// a fresh directory of the output directory.
+    pub fn start_field_export(&mut self, path: Option<PathBuf>) -> io::Result<()> {
+        let path = path.unwrap_or_else(|| export::timestamped_path("fields", None));
+        let exporter = FieldExporter::start(path, self.field_format, self.field_stride)?;
+        self.notify(format!("Exporting fields to {}", exporter.path.display()));
+        self.exporter = Some(exporter);
+        Ok(())
+    }
+
+    /// Finishes the field export, returning the number of steps written and where.
+    pub fn stop_field_export(&mut self) -> io::Result<Option<(usize, PathBuf)>> {
+        let Some(exporter) = self.exporter.take() else {
+            return Ok(None);
+        };
+        let (written, path) = (exporter.written, exporter.path.clone());
+        exporter.finish()?;
+        self.notify(format!("Exported {written} steps to {}", path.display()));
+        Ok(Some((written, path)))
+    }
+
+    fn toggle_field_export(&mut self) {
+        let result = if self.is_exporting() {
+            self.stop_field_export().map(|_| ())
+        } else {
+            self.start_field_export(None)
+        };
+        if let Err(e) = result {
+            self.notify(format!("Field export failed: {e}"));
+        }
+    }
+
+    fn export_step(&mut self) {
+        let Some(exporter) = &mut self.exporter else {
+            return;
+        };
+
+        if let Err(e) = exporter.capture(&self.fluid, self.time) {
+            self.exporter = None;
+            self.notify(format!("Field export stopped: {e}"));
+        }
+    }
+
+    pub fn is_logging(&self) -> bool {
+        self.diagnostics.is_some()
+    }
+
+    /// Starts appending the diagnostics of every step to `path`, or to a fresh file
+    /// of the output directory.
+    pub fn start_diagnostics(&mut self, path: Option<PathBuf>) -> io::Result<()> {
+        let path = path.unwrap_or_else(|| export::timestamped_path("diagnostics", Some(self.log_format.extension())));
+        let log = DiagnosticsLog::start(path, self.log_format)?;
+        self.notify(format!("Logging diagnostics to {}", log.path.display()));
+        self.diagnostics = Some(log);
+        Ok(())
+    }
+
+    /// Finishes the diagnostics log, returning the number of steps written and where.
+    pub fn stop_diagnostics(&mut self) -> io::Result<Option<(usize, PathBuf)>> {
+        let Some(log) = self.diagnostics.take() else {
+            return Ok(None);
+        };
+        let (written, path) = (log.written, log.path.clone());
+        log.finish()?;
+        self.notify(format!("Logged {written} steps to {}", path.display()));
+        Ok(Some((written, path)))
+    }
+
+    fn toggle_diagnostics(&mut self) {
+        let result = if self.is_logging() {
+            self.stop_diagnostics().map(|_| ())
+        } else {
+            self.start_diagnostics(None)
+        };
+        if let Err(e) = result {
+            self.notify(format!("Diagnostics log failed: {e}"));
+        }
+    }
+
+    fn log_step(&mut self, dt: f64) {
+        let Some(log) = &mut self.diagnostics else {
+            return;
+        };
+
+        if let Err(e) = log.append(&self.fluid, self.time, dt) {
+            self.diagnostics = None;
+            self.notify(format!("Diagnostics log stopped: {e}"));
+        }
+    }
+
+    fn live_params(&self) -> LiveParams {
+        LiveParams {
+            diffusion_rate: self.diffusion_rate,
+            pressure_iters: self.pressure_iters,
+            diffusion_iters: self.diffusion_iters,
+            conserve_mass: self.conserve_mass,
+            emitter_density: self.emitter_density,
+            emitter_speed: self.emitter_speed,
+            emitter_angle: self.emitter_angle,
+            sink_rate: self.sink_rate,
+        }
+    }
+
+    fn set_live_params(&mut self, params: LiveParams) {
+        self.diffusion_rate = params.diffusion_rate;
+        self.fluid.diffusion_rate = params.diffusion_rate;
+        self.pressure_iters = params.pressure_iters;
+        self.diffusion_iters = params.diffusion_iters;
+        self.conserve_mass = params.conserve_mass;
+        self.emitter_density = params.emitter_density;
+        self.emitter_speed = params.emitter_speed;
+        self.emitter_angle = params.emitter_angle;
+        self.sink_rate = params.sink_rate;
+    }
+
+    pub fn is_recording_input(&self) -> bool {
+        self.input_recorder.is_some()
+    }
+
+    /// Starts recording the input to `path`, or to a fresh file of the output directory.
+    ///
+    /// The simulation steps at the fixed recording time step until the recording stops.
+    pub fn start_input_recording(&mut self, path: Option<PathBuf>) -> io::Result<()> {
+        let path = path.unwrap_or_else(|| export::timestamped_path("input", Some("jsonl")));
+        let scenario = self.scenario.as_ref().map(|s| s.path.clone());
+        let clock = Clock { time: self.time, paused: self.paused, step_frame: self.step_frame };
+        let recorder = InputRecorder::start(path, &self.fluid, self.live_params(), scenario, clock)?;
+        self.notify(format!("Recording input to {}", recorder.path.display()));
+        self.input_recorder = Some(recorder);
+        Ok(())
+    }
+
+    pub fn stop_input_recording(&mut self) {
+        let Some(recorder) = self.input_recorder.take() else {
+            return;
+        };
+        let (frames, path) = (recorder.frame, recorder.path.clone());
+        match recorder.finish() {
+            Ok(()) => self.notify(format!("Recorded {frames} frames of input to {}", path.display())),
+            Err(e) => self.notify(format!("Input recording failed: {e}")),
+        }
+    }
+
+    fn toggle_input_recording(&mut self) {
+        if self.is_recording_input() {
+            self.stop_input_recording();
+        } else if let Err(e) = self.start_input_recording(None) {
+            self.notify(format!("Input recording failed: {e}"));
+        }
+    }
+
+    /// Restarts from the state an input recording began with and replays its input
+    /// at its time step, in place of the user's.
+    pub fn start_replay(&mut self, path: &Path) -> io::Result<()> {
+        let replay = InputReplay::load(path)?;
+        match &replay.scenario {
+            Some(scenario) => self.load_scenario(scenario)?,
+            None => self.scenario = None,
+        }
+        self.restore(replay.load_start()?);
+        if let Some(params) = replay.start_params {
+            self.set_live_params(params);
+        }
+        self.time = replay.start_clock.time;
+        self.paused = replay.start_clock.paused;
+        self.step_frame = replay.start_clock.step_frame;
+        self.notify(format!("Replaying {}", replay.path.display()));
+        self.replay = Some(replay);
+        Ok(())
+    }
+
+    /// Number of frames left in the replay.
+    pub fn replay_remaining(&self) -> u64 {
+        self.replay.as_ref().map_or(0, |r| r.length.saturating_sub(r.frame))
+    }
+
+    /// Adapts the buffers to a new view size and resamples the simulation onto a matching grid.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if (width, height) == (self.width, self.height) || width == 0 || height == 0 {
+            return;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.buffer = vec![0u32; width * height];
+        self.lic = Lic::new(width, height, self.lic.length);
+
+        // Restored snapshots, replays and scenarios stay on the grid they were saved, recorded
+        // or defined on, only the view follows the new size
+        let (grid_width, grid_height) = if self.fixed_grid {
+            (self.fluid.width, self.fluid.height)
+        } else {
+            ViewTransform::grid_size(width, height, self.precision)
+        };
+        if (grid_width, grid_height) != (self.fluid.width, self.fluid.height) {
+            if self.input_recorder.is_some() {
+                // Recordings are in grid cells, they can't follow the resampling
+                self.stop_input_recording();
+            }
+            self.tracers.rescale((self.fluid.width, self.fluid.height), (grid_width, grid_height));
+            self.trails.clear();
+            self.last_cell = None;
+            self.fluid = self.fluid.resampled(grid_width, grid_height);
+        }
+        self.view.resize(width, height, grid_width, grid_height);
+    }
+
+    /// Handles one frame of input, advances the simulation by `dt` seconds and renders it.
+    pub fn frame(&mut self, input: &FrameInput, dt: f64) {
+        if dt <= 0.0 {
+            return;
+        }
+
+        let now = std::time::Instant::now();
+        self.frame_count += 1;
+        let elapsed = now.duration_since(self.last_fps_update).as_secs_f64();
+        if elapsed >= 0.5 {
+            self.fps = self.frame_count as f64 / elapsed;
+            self.frame_count = 0;
+            self.last_fps_update = now;
+        }
+
+        if self.message.as_ref().is_some_and(|(_, at)| at.elapsed().as_secs_f64() > 4.0) {
+            self.message = None;
+        }
+
+        let stroke = self.handle_mouse(input, dt);
+        let (keys, stroke) = match self.replay.as_mut().map(|replay| replay.next_frame()) {
+            Some(recorded) => {
+                if let Some(params) = recorded.params {
+                    self.set_live_params(params);
                 }
+                let mut keys = recorded.keys;
+                keys.extend(input.pressed.iter().copied().filter(|&key| replay::is_file_key(key)));
+                (keys, recorded.stroke)
             }
+            None => (input.pressed.clone(), stroke),
+        };
 
-            last_mouse = (mx, my);
+        // Toggled before recording so the first recorded frame is the one right after the start
+        // snapshot, never during a replay which would record from the replayed state
+        if keys.contains(&Key::P) && self.replay.is_none() {
+            self.toggle_input_recording();
+        }
 
-            if !self.paused || self.step_frame > 0 {
-                fluid.step(dt.min(0.05), self.pressure_iters, self.diffusion_iters); // clamp dt for stability
-                self.step_frame = self.step_frame.saturating_sub(1);
+        // Recordings and replays run at a fixed time step to stay reproducible
+        let dt = match (&self.replay, &self.input_recorder) {
+            (Some(replay), _) => replay.dt,
+            (None, Some(_)) => replay::RECORD_DT,
+            (None, None) => dt,
+        };
+
+        let params = self.live_params();
+        if let Some(recorder) = &mut self.input_recorder {
+            if let Err(e) = recorder.record(&keys, stroke.as_ref(), params) {
+                self.input_recorder = None;
+                self.notify(format!("Input recording stopped: {e}"));
+            }
+        }
+
+        self.handle_keys(&keys);
+        if let Some(stroke) = &stroke {
+            self.apply_stroke(stroke, &keys, dt);
+        }
+
+        let stepped = !self.paused || self.step_frame > 0;
+        if stepped {
+            let dt = dt.min(MAX_DT);
+            // Restored snapshots and scenarios bring their own simulation, the settings stay the window's
+            self.fluid.conserve_mass = self.conserve_mass;
+            self.fluid.measure = self.measure || self.show_stats || self.is_logging();
+            let budget = self.fluid.conserve_mass || self.fluid.measure;
+            let start_mass = if budget { self.fluid.total_mass() } else { 0.0 };
+            if let Some(scenario) = &self.scenario {
+                scenario.apply(&mut self.fluid, self.time, dt);
+            }
+            self.fluid.step(dt, self.pressure_iters, self.diffusion_iters);
+            // Scenario emitters are sources of the step too
+            if budget {
+                self.fluid.mass.sources += self.fluid.mass.start - start_mass;
+                self.fluid.mass.start = start_mass;
             }
+            self.tracers.advect(&self.fluid, dt);
+            self.time += dt;
+            self.log_step(dt);
+            self.step_frame = self.step_frame.saturating_sub(1);
+        }
 
+        if self.draw {
+            self.render();
+        }
 
-            self.buffer.fill(0);
+        // Only simulated frames are recorded so pauses don't show up in the sequence
+        if stepped {
+            self.record_frame();
+            self.export_step();
+        }
 
-            for y in 0..fluid.height {
-                let base_y = y * self.precision * self.width;
-                for x in 0..fluid.width {
-                    let d = fluid.get_density(x, y).clamp(0.0, 1.0);
+        if self.replay.as_ref().is_some_and(|r| r.is_finished()) {
+            self.replay = None;
+            self.notify("Replay finished".to_string());
+        }
+    }
 
-                    let r = (d * ((self.max_color >> 0) & 0xFF) as f64) as u8;
-                    let g = (d * ((self.max_color >> 8) & 0xFF) as f64) as u8;
-                    let b = (d * ((self.max_color >> 16) & 0xFF) as f64) as u8;
+    fn handle_keys(&mut self, keys: &[Key]) {
+        let pressed = |key| keys.contains(&key);
 
-                    let color =
-                        ((b as u32) << 16) |
-                        ((g as u32) << 8) |
-                        (r as u32);
+        if pressed(Key::Space) {
+            self.paused = !self.paused;
+        }
 
-                    let base_x = x * self.precision;
-                    for py in 0..self.precision {
-                        let row_offset = base_y + py * self.width;
-                        for px in 0..self.precision {
-                            self.buffer[row_offset + base_x + px] = color;
-                        }
+        if self.paused {
+            if pressed(Key::Right) {
+                self.step_frame = 1;
+            }
+            if pressed(Key::Up) {
+                self.step_frame = 10;
+            }
+        }
+
+        if pressed(Key::C) {
+            self.colormap_kind = self.colormap_kind.next();
+            self.colormap = Colormap::new(self.colormap_kind, &self.gradient);
+        }
+        if pressed(Key::F) {
+            self.field = self.field.next();
+        }
+        if pressed(Key::I) {
+            self.use_lic = !self.use_lic;
+        }
+        if pressed(Key::O) {
+            self.lic_modulate = !self.lic_modulate;
+        }
+        if pressed(Key::B) {
+            self.smooth = !self.smooth;
+        }
+        if pressed(Key::V) {
+            self.show_velocity = !self.show_velocity;
+        }
+        if pressed(Key::H) {
+            self.show_stats = !self.show_stats;
+        }
+        if pressed(Key::Z) {
+            self.eraser = !self.eraser;
+            self.notify(format!("Eraser {}", if self.eraser { "on" } else { "off" }));
+        }
+        if pressed(Key::Y) {
+            self.show_trails = !self.show_trails;
+            self.trails.clear();
+        }
+        if pressed(Key::Backspace) {
+            self.tracers.clear();
+            self.trails.clear();
+        }
+        if pressed(Key::Home) {
+            self.view.reset();
+        }
+        if pressed(Key::F5) {
+            self.save_snapshot();
+        }
+        if pressed(Key::F9) {
+            self.load_latest_snapshot();
+        }
+        if pressed(Key::R) {
+            self.toggle_recording();
+        }
+        if pressed(Key::E) {
+            self.export_fields();
+        }
+        if pressed(Key::X) {
+            self.toggle_field_export();
+        }
+        if pressed(Key::D) {
+            self.toggle_diagnostics();
+        }
+    }
+
+    /// Zooms and pans the view, and turns the cursor into a brush stroke in grid space.
+    fn handle_mouse(&mut self, input: &FrameInput, dt: f64) -> Option<Stroke> {
+        let Some((mx, my)) = input.mouse else {
+            self.last_mouse = None;
+            self.last_cell = None;
+            return None;
+        };
+        let last_mouse = self.last_mouse.unwrap_or((mx, my));
+        self.last_mouse = Some((mx, my));
+
+        if input.scroll != 0.0 {
+            self.view.zoom_at(mx, my, 1.1f64.powf(input.scroll.signum()));
+        }
+        if input.middle {
+            self.view.pan_by(mx - last_mouse.0, my - last_mouse.1);
+        }
+        let (ppc_x, ppc_y) = self.view.pixels_per_cell();
+        let (x, y) = self.view.screen_to_grid(mx, my);
+        let from = self.last_cell.replace((x, y));
+
+        // Forces follow the cursor speed over the grid, not over the zoomed screen
+        let fx = (mx - last_mouse.0) / dt / self.view.zoom;
+        let fy = (my - last_mouse.1) / dt / self.view.zoom;
+
+        Some(Stroke {
+            x,
+            y,
+            from,
+            radius: (self.particle_radius as f64 / ppc_x, self.particle_radius as f64 / ppc_y),
+            density: input.left,
+            impulse: input.right.then_some((fx, fy)),
+            brush: Brush {
+                shape: self.brush_shape,
+                strength: self.brush_strength,
+                force: self.brush_force,
+                erase: self.eraser,
+            },
+        })
+    }
+
+    /// Seeds tracers and places emitters and sinks at the stroke, and paints its density and forces.
+    fn apply_stroke(&mut self, stroke: &Stroke, keys: &[Key], dt: f64) {
+        if keys.contains(&Key::T) {
+            self.tracers.seed_at(stroke.x, stroke.y, stroke.radius.0.max(1.0), 200);
+        }
+        if keys.contains(&Key::L) {
+            let height = self.fluid.height;
+            self.tracers.seed_line((stroke.x, 1.0), (stroke.x, (height - 2) as f64), height * 2);
+        }
+
+        let radius = 0.5 * (stroke.radius.0 + stroke.radius.1);
+        if keys.contains(&Key::N) {
+            let angle = self.emitter_angle.to_radians();
+            self.fluid.emitters.push(Emitter {
+                x: stroke.x,
+                y: stroke.y,
+                radius,
+                density: self.emitter_density,
+                // Angles go counterclockwise on screen, where y points down
+                velocity: (self.emitter_speed > 0.0)
+                    .then(|| (self.emitter_speed * angle.cos(), -self.emitter_speed * angle.sin())),
+            });
+        }
+        if keys.contains(&Key::M) {
+            self.fluid.sinks.push(Sink { x: stroke.x, y: stroke.y, radius, rate: self.sink_rate });
+        }
+        if keys.contains(&Key::Delete) {
+            self.fluid.emitters.retain(|e| !e.contains(stroke.x, stroke.y));
+            self.fluid.sinks.retain(|s| !s.contains(stroke.x, stroke.y));
+        }
+
+        let to = (stroke.x, stroke.y);
+        let segment = (stroke.from.unwrap_or(to), to);
+        stroke.brush.paint(&mut self.fluid, segment, stroke.radius, stroke.density, stroke.impulse, dt);
+    }
+
+    fn render(&mut self) {
+        let fluid = &self.fluid;
+        let view = &self.view;
+
+        if self.use_lic {
+            self.lic.render(fluid, view, &mut self.lic_values);
+
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let mut v = self.lic_values[x + y * self.width];
+                    if self.lic_modulate {
+                        let (gx, gy) = view.screen_to_grid(x as f64 + 0.5, y as f64 + 0.5);
+                        let d = if self.smooth {
+                            fluid_sim::FluidSim::sample_field(&fluid.density, fluid.width, fluid.height, gx, gy)
+                        } else {
+                            let cx = (gx.round().max(0.0) as usize).min(fluid.width - 1);
+                            let cy = (gy.round().max(0.0) as usize).min(fluid.height - 1);
+                            fluid.get_density(cx, cy)
+                        };
+                        v *= d.clamp(0.0, 1.0);
                     }
+                    self.buffer[x + y * self.width] = self.colormap.map(v);
                 }
             }
+        } else {
+            self.field.sample(fluid, &mut self.values);
+            render::upscale(&self.values, view, self.smooth, &self.colormap, &mut self.buffer);
+        }
+
+        overlay::draw_obstacles(&mut self.buffer, fluid, view, 0x606060);
 
-            self.window
-                .update_with_buffer(&self.buffer, self.width, self.height)
-                .unwrap();
+        if self.show_trails {
+            self.trails.decay();
+            for &(x, y) in &self.tracers.particles {
+                self.trails.stamp(x, y);
+            }
+            self.trails.composite(&mut self.buffer, view, 0xFFD040);
+        } else {
+            for &(x, y) in &self.tracers.particles {
+                let (sx, sy) = view.grid_to_screen(x, y);
+                overlay::draw_point(&mut self.buffer, self.width, self.height, sx, sy, 0xFFD040);
+            }
+        }
+
+        for emitter in &fluid.emitters {
+            overlay::draw_source(&mut self.buffer, view, emitter.x, emitter.y, emitter.radius, emitter.velocity, 0x40C0FF);
+        }
+        for sink in &fluid.sinks {
+            overlay::draw_source(&mut self.buffer, view, sink.x, sink.y, sink.radius, None, 0xFF4040);
+        }
+
+        if self.show_velocity {
+            overlay::draw_velocity_arrows(&mut self.buffer, fluid, view, 0x00FF40);
         }
     }
+
+    /// Statistics of the flow and the solver after the last step, a line each, for the
+    /// app to draw over the view when `show_stats` is on.
+    pub fn stats_lines(&self) -> Vec<String> {
+        let stats = FlowStats::measure(&self.fluid);
+        let timings = &self.fluid.timings;
+        let ms = |seconds: f64| seconds * 1000.0;
+        // Only steps taken with the statistics shown are measured
+        let scientific = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{v:.2e}"));
+        vec![
+            format!("FPS {:.1}  Grid {}x{}", self.fps, self.fluid.width, self.fluid.height),
+            format!("Step {:.2} ms", ms(timings.total())),
+            format!("  Sources  {:.2}", ms(timings.sources)),
+            format!("  Advect u {:.2}", ms(timings.advect_velocity)),
+            format!("  Project  {:.2}", ms(timings.projection)),
+            format!("  Diffuse  {:.2}", ms(timings.diffusion)),
+            format!("  Advect d {:.2}", ms(timings.advect_density)),
+            format!("Mass      {:.3}", stats.mass),
+            format!("Max speed {:.3}", stats.max_speed),
+            format!("Kinetic E {:.3e}", stats.kinetic_energy),
+            format!("Enstrophy {:.3e}", stats.enstrophy),
+            format!("Max div   {}", scientific(self.fluid.max_divergence)),
+            format!("Residual  {}", scientific(self.fluid.pressure_residual)),
+        ]
+    }
 }
 
+fn latest_snapshot() -> Option<PathBuf> {
+    std::fs::read_dir(SNAPSHOT_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "fsim"))
+        .max_by_key(|path| path.metadata().and_then(|m| m.modified()).ok())
+}